@@ -2,5 +2,13 @@ mod rtf;
 mod translator;
 mod utils;
 
+pub use rtf::error::{SkipReason, SplitError, SplitReason};
+pub use rtf::header::{Color, Font, FontFamily, Header, Info, Style, StyleKind};
 pub use rtf::logger::Logger;
+pub use rtf::rtf_cell::CellContext;
 pub use rtf::worker::{Worker, WorkerParam};
+pub use translator::{
+    BatchRequest, Budget, Completion, DryRunReport, LlmClient, LlmConfig, ModelPrice, Passthrough,
+    Term, TranslateError, TranslateRequest, TranslationBackend, Usage,
+};
+pub use utils::Language;
@@ -1,6 +1,6 @@
 use std::{env, path::Path, thread, time::Duration};
 
-use transient::{Worker, WorkerParam};
+use transient::{Language, LlmClient, LlmConfig, Worker, WorkerParam};
 
 fn main() -> anyhow::Result<()> {
     let llm_api_key = env::var("LLM_API_KEY")?;
@@ -22,13 +22,21 @@ fn main() -> anyhow::Result<()> {
 
     let destination_dir = Path::new(r"D:\projects\rusty\playground\rtf\.sample_data\test\result");
 
-    let worker = Worker::new(&WorkerParam {
+    let worker = Worker::new(WorkerParam {
         workspace,
         outputs: &outputs,
         destination_dir,
-        llm_api_key: &llm_api_key,
+        backend: Box::new(LlmClient::new(&llm_api_key)),
+        llm_config: LlmConfig::default(),
+        translation_memory: None,
+        glossary: None,
+        budget: None,
+        source_language: Language::Chinese,
+        target_language: Language::English,
+        font_mapping: None,
+        reflow: false,
     })?;
-    worker.extract_translate();
+    worker.extract_translate(false);
 
     loop {
         let log = worker.read_log()?;
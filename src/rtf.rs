@@ -1,13 +1,16 @@
-pub mod control_word;
+pub mod error;
 pub mod font_definition;
 pub mod footnote;
+pub mod header;
+pub mod layout;
+pub mod lexer;
 pub mod logger;
 pub mod processor;
 pub mod rtf_cell;
 pub mod sunderer;
 pub mod symbol;
 pub mod template;
-pub mod utils;
+pub mod tree;
 pub mod worker;
 
 // #[test]
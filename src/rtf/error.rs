@@ -0,0 +1,86 @@
+use std::{error::Error, fmt::Display};
+
+/// failure of splitting an rtf into template and cells, offset is the byte where it happened
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitError {
+    pub offset: usize,
+    pub reason: SplitReason,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SplitReason {
+    /// a backslash without anything following it at the end of file
+    TrailingBackslash,
+    /// \' not followed by 2 hex digits
+    InvalidHex,
+    /// name of control word is longer than 32 letters
+    ControlWordTooLong,
+    /// parameter of control word is not a 32 bits integer
+    InvalidParameter(String),
+    /// \binN claims more bytes than left in file
+    BinaryOverflow(usize),
+    /// a closing bracket without opening bracket
+    UnexpectedClosing,
+    /// a group which is not closed at the end of file
+    Unclosed,
+    /// contents of a cell or footnote are not utf-8
+    InvalidUtf8,
+    /// failed to read rtf or write template
+    Io(String),
+}
+
+impl SplitError {
+    pub fn new(offset: usize, reason: SplitReason) -> Self {
+        SplitError { offset, reason }
+    }
+
+    pub fn io(offset: usize, e: impl Display) -> Self {
+        SplitError::new(offset, SplitReason::Io(e.to_string()))
+    }
+}
+
+impl Display for SplitReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SplitReason::TrailingBackslash => write!(f, "a backslash at the end of rtf"),
+            SplitReason::InvalidHex => write!(f, "invalid hex escape"),
+            SplitReason::ControlWordTooLong => write!(f, "control word is too long"),
+            SplitReason::InvalidParameter(name) => write!(f, "invalid parameter of \\{}", name),
+            SplitReason::BinaryOverflow(size) => {
+                write!(f, "\\bin{} exceeds the end of rtf", size)
+            }
+            SplitReason::UnexpectedClosing => write!(f, "unexpected closing bracket"),
+            SplitReason::Unclosed => write!(f, "group is not closed"),
+            SplitReason::InvalidUtf8 => write!(f, "content is not valid utf-8"),
+            SplitReason::Io(message) => write!(f, "io failure: {}", message),
+        }
+    }
+}
+
+impl Display for SplitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at byte {}", self.reason, self.offset)
+    }
+}
+
+impl Error for SplitError {}
+
+/// why an output is skipped by worker and left untranslated
+#[derive(Debug, Clone, PartialEq)]
+pub enum SkipReason {
+    /// failed to split it into cells and template
+    Split(SplitError),
+    /// failed to translate cells of it, with the chain of causes
+    Translate(String),
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::Split(e) => write!(f, "failed to split it into cells and template: {}", e),
+            SkipReason::Translate(message) => {
+                write!(f, "failed to translate cells of it: {}", message)
+            }
+        }
+    }
+}
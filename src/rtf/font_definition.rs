@@ -1,108 +1,243 @@
-use std::collections::HashSet;
+use std::{fs, path::Path};
 
-use super::{
-    symbol::{FONT_TBL, SLASH},
-    utils,
+use serde::Deserialize;
+
+use crate::{
+    rtf::{
+        header::{Font, Header},
+        processor::Decoder,
+        tree,
+    },
+    utils::Language,
 };
 
-/// find out codes of fonts in rtf files, return a hashset of font codes defined in rtf
-///
-/// ```rust
-/// #[test]
-/// fn test_fonts() {
-///     let filepath = Path::new(r"D:\projects\rusty\playground\rtf\.sample_data\cn.rtf");
-///     let bytes = fs::read(filepath).unwrap();
-///     let font_list = font_set(&bytes);
-///     assert_eq!(3, font_list.len());
-// }
-/// ```
-pub fn font_set(bytes: &[u8]) -> HashSet<String> {
-    let mut fonts = HashSet::new();
-    let mut pointer = 0;
-    while pointer < bytes.len() {
-        if let Some(pos) = utils::pattern_position(&FONT_TBL, &bytes, pointer) {
-            pointer = pos.0;
-        } else {
-            pointer += 1;
-            continue;
-        }
-        if let Some(font_groups) = utils::group_position(&bytes, pointer - 1, bytes.len()) {
-            while pointer < font_groups.1 {
-                if let Some(font_detail) = utils::group_position(&bytes, pointer, font_groups.1) {
-                    if let Some(font) = font_code(&bytes[font_detail.0..font_detail.1]) {
-                        fonts.insert(font);
-                    }
-                    pointer = font_detail.1;
-                } else {
-                    pointer += 1;
-                }
-            }
-            break;
-        }
+/// replace a font in font table by name, such as SimSun => Times New Roman
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct FontRule {
+    pub source: String,
+    pub target: String,
+    /// \fcharset of target font, the one of source font is kept if missing
+    pub charset: Option<u32>,
+}
+
+/// replace a charset in font table, such as \fcharset134 => \fcharset0
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CharsetRule {
+    pub source: u32,
+    pub target: u32,
+}
+
+/// fonts of translated outputs configured by user, which go before the fonts of target language
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct FontMapping {
+    #[serde(default)]
+    pub font: Vec<FontRule>,
+    #[serde(default)]
+    pub charset: Vec<CharsetRule>,
+}
+
+impl FontMapping {
+    pub fn from_file(filepath: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(filepath)?;
+        Ok(toml::from_str(&content)?)
     }
 
-    fonts
+    fn is_empty(&self) -> bool {
+        self.font.is_empty() && self.charset.is_empty()
+    }
 }
 
-/// find out the font code in the target, such as f1, f2 etc
-/// ```rust
-/// #[test]
-/// fn test_font_code() {
-///     let source = br"{\f1\froman\fprq2\fcharset0 SimSun;}".to_vec();
-///     let font = font_code(&source);
-///     assert_eq!(Some("f1".to_string()), font);
-/// }
-/// ```
-fn font_code(source: &[u8]) -> Option<String> {
-    let mut start = 0;
-    let mut end = start;
-    while start < source.len() {
-        if let Some(c) = source.get(start) {
-            if SLASH.ne(c) {
-                start += 1;
-                continue;
-            }
-        } else {
-            return None;
+/// replace fonts in font table by font mapping, fonts of source language which are not mapped
+/// are replaced by the font of target language
+pub struct FontSubstitution {
+    source: Language,
+    target: Language,
+    mapping: FontMapping,
+}
+
+impl FontSubstitution {
+    pub fn new(source: Language, target: Language, mapping: &FontMapping) -> Self {
+        FontSubstitution {
+            source,
+            target,
+            mapping: mapping.clone(),
         }
-        start += 1;
-        end += start + 1;
-        while end < source.len() {
-            if let Some(c) = source.get(end) {
-                if SLASH.ne(c) {
-                    end += 1;
-                    continue;
-                } else {
-                    break;
-                }
+    }
+
+    /// substitute entries in font table of rtf, entries not substituted and the rest of rtf are
+    /// kept byte for byte
+    pub fn apply(&self, content: &str) -> anyhow::Result<String> {
+        if self.source == self.target && self.mapping.is_empty() {
+            return Ok(content.into());
+        }
+        let bytes = content.as_bytes();
+        let root = tree::parse(bytes)?;
+        let fonttbl = match root.find("fonttbl") {
+            Some(fonttbl) => fonttbl,
+            None => return Ok(content.into()),
+        };
+        let decoder = Decoder::new(&Header::from_tree(&root, bytes));
+        // byte ranges of entries and their substitutions
+        let entries = match fonttbl.groups().count() {
+            // a table with only one font may not enclose it in a group
+            0 => Font::parse(&fonttbl.children, bytes)
+                .and_then(|font| {
+                    let substitution = self.substitute(&font, &decoder);
+                    (substitution != font).then(|| {
+                        (
+                            fonttbl.start,
+                            fonttbl.end,
+                            format!(r"{{\fonttbl{}}}", substitution.to_rtf()),
+                        )
+                    })
+                })
+                .into_iter()
+                .collect::<Vec<_>>(),
+            _ => fonttbl
+                .groups()
+                .filter_map(|group| {
+                    let font = Font::parse(&group.children, bytes)?;
+                    let substitution = self.substitute(&font, &decoder);
+                    (substitution != font).then(|| (group.start, group.end, substitution.to_rtf()))
+                })
+                .collect(),
+        };
+        let mut result = String::with_capacity(content.len());
+        let mut position = 0;
+        for (start, end, entry) in entries {
+            result.push_str(&content[position..start]);
+            result.push_str(&entry);
+            position = end;
+        }
+        result.push_str(&content[position..]);
+        Ok(result)
+    }
+
+    /// font after substitution, names escaped such as \'cb\'ce\'cc\'e5 are decoded before matching
+    fn substitute(&self, font: &Font, decoder: &Decoder) -> Font {
+        let name = decoder.decode(&font.name, Some(font.code));
+        let name = name.trim();
+        let rule = self
+            .mapping
+            .font
+            .iter()
+            .find(|rule| rule.source.eq_ignore_ascii_case(name));
+        let (name, charset) = match rule {
+            Some(rule) => (rule.target.clone(), rule.charset.or(font.charset)),
+            None if self.source != self.target && self.source.uses_font(name, font.charset) => {
+                let (name, charset) = self.target.font();
+                (name.to_string(), Some(charset))
             }
-            break;
+            None => (font.name.clone(), font.charset),
+        };
+        let charset = charset.map(|charset| {
+            self.mapping
+                .charset
+                .iter()
+                .find(|rule| rule.source == charset)
+                .map_or(charset, |rule| rule.target)
+        });
+        Font {
+            name,
+            charset,
+            ..font.clone()
         }
-        break;
     }
-    Some(String::from_utf8(source.get(start..end).unwrap().to_vec()).unwrap())
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{fs, path::Path};
-
     use super::*;
     #[test]
-    fn test_fonts() {
-        let filepath = Path::new(r"D:\projects\rusty\playground\rtf\.sample_data\cn.rtf");
-        let bytes = fs::read(filepath).unwrap();
-        let font_list = font_set(&bytes);
-        assert_eq!(3, font_list.len());
-        let filepath = Path::new(r"D:\projects\rusty\playground\rtf\.sample_data\en.rtf");
-        let bytes = fs::read(filepath).unwrap();
-        let font_list = font_set(&bytes);
-        assert_eq!(1, font_list.len());
+    fn font_substitution_test() {
+        let substitution = FontSubstitution::new(
+            Language::Chinese,
+            Language::English,
+            &FontMapping::default(),
+        );
+        let source = r"{\fonttbl{\f1\froman\fprq2\fcharset0 SimSun;}{\f2\fnil\fcharset134 \'cb\'ce\'cc\'e5;}{\f3\fswiss Arial;}}";
+        assert_eq!(
+            r"{\fonttbl{\f1\froman\fprq2\fcharset0 Times New Roman;}{\f2\fnil\fcharset0 Times New Roman;}{\f3\fswiss Arial;}}",
+            substitution.apply(source).unwrap()
+        );
+        let substitution = FontSubstitution::new(
+            Language::English,
+            Language::Chinese,
+            &FontMapping::default(),
+        );
+        assert_eq!(
+            r"{\fonttbl{\f0\froman\fcharset134 SimSun;}}",
+            substitution
+                .apply(r"{\fonttbl{\f0\froman Times New Roman;}}")
+                .unwrap()
+        );
+        let substitution = FontSubstitution::new(
+            Language::Chinese,
+            Language::Chinese,
+            &FontMapping::default(),
+        );
+        assert_eq!(
+            r"{\fonttbl{\f1\fcharset134 SimSun;}}",
+            substitution
+                .apply(r"{\fonttbl{\f1\fcharset134 SimSun;}}")
+                .unwrap()
+        );
     }
+
+    #[test]
+    fn font_mapping_test() {
+        let mapping: FontMapping = toml::from_str(
+            r#"
+            [[font]]
+            source = "SimHei"
+            target = "Arial"
+
+            [[font]]
+            source = "SimSun"
+            target = "Courier New"
+            charset = 0
+
+            [[charset]]
+            source = 134
+            target = 0
+            "#,
+        )
+        .unwrap();
+        let substitution = FontSubstitution::new(Language::Chinese, Language::English, &mapping);
+        let source = r"{\fonttbl{\f1\froman\fprq2\fcharset134 SimSun;}{\f2\fnil\fcharset134 SimHei;}{\f3\fnil\fcharset134 KaiTi;}{\f4\fswiss Arial;}}";
+        assert_eq!(
+            r"{\fonttbl{\f1\froman\fprq2\fcharset0 Courier New;}{\f2\fnil\fcharset0 Arial;}{\f3\fnil\fcharset0 Times New Roman;}{\f4\fswiss Arial;}}",
+            substitution.apply(source).unwrap()
+        );
+        // mapping is applied even if languages are the same
+        let substitution = FontSubstitution::new(Language::Chinese, Language::Chinese, &mapping);
+        // the entry of a table with only one font is enclosed in a group
+        assert_eq!(
+            r"{\fonttbl{\f1\fcharset0 Arial;}}",
+            substitution
+                .apply(r"{\fonttbl\f1\fcharset134 SimHei;}")
+                .unwrap()
+        );
+    }
+
     #[test]
-    fn test_font_code() {
-        let source = br"{\f1\froman\fprq2\fcharset0 SimSun;}".to_vec();
-        let font = font_code(&source);
-        assert_eq!(Some("f1".to_string()), font);
+    fn font_substitution_escaped_test() {
+        let mapping: FontMapping = toml::from_str(
+            r#"
+            [[font]]
+            source = "黑体"
+            target = "Arial"
+            "#,
+        )
+        .unwrap();
+        let substitution = FontSubstitution::new(Language::Chinese, Language::English, &mapping);
+        // names in hex escapes with alternative fonts, font table in the line of a placeholder
+        let source = r"{\rtf1\ansi\ansicpg936{\fonttbl{\f0\froman\fcharset0 Times New Roman;}{\f1\fnil\fcharset134 \'cb\'ce\'cc\'e5{\*\falt SimSun};}{\f2\fnil\fcharset134 \'ba\'da\'cc\'e5;}}{\info{\title {#0#}}}
+\f1{#1#}}";
+        assert_eq!(
+            r"{\rtf1\ansi\ansicpg936{\fonttbl{\f0\froman\fcharset0 Times New Roman;}{\f1\fnil\fcharset0 Times New Roman;}{\f2\fnil\fcharset134 Arial;}}{\info{\title {#0#}}}
+\f1{#1#}}",
+            substitution.apply(source).unwrap()
+        );
     }
 }
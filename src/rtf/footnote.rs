@@ -1,32 +1,65 @@
-use super::{symbol::CHCBPAT0, utils::pattern_position};
+use super::tree::{Group, Node};
 
-/// to find out the position of first footnote in given bytes
-pub fn footnote_position(source: &[u8], range: (usize, usize)) -> Option<(usize, usize)> {
-    if source[range.0..range.0 + 6].eq(r"\f2\fs".as_bytes()) {
-        let chcbpat0_position = pattern_position(CHCBPAT0.as_bytes(), source, range.0);
-        if let Some(chcbpat0_position) = chcbpat0_position {
-            Some((range.0, chcbpat0_position.1))
-        } else {
-            None
+/// byte ranges of footnotes, which are paragraphs after the final table row of each page
+///
+/// a page ends at \page or \sect, or the end of document. text between the last \row of a page
+/// and the end of page is footnote, while text followed by another row on the same page is not.
+/// page headers and footers are skipped
+pub fn footnote_regions(root: &Group) -> Vec<(usize, usize)> {
+    let mut regions = vec![];
+    // end of the latest \row on current page
+    let mut start = None;
+    walk(root, &mut start, &mut regions);
+    if let Some(start) = start {
+        regions.push((start, root.end));
+    }
+    regions
+}
+
+fn walk(group: &Group, start: &mut Option<usize>, regions: &mut Vec<(usize, usize)>) {
+    for node in group.children.iter() {
+        match node {
+            Node::Group(child) if child.is_hidden() || child.is_header() || child.is_footer() => {}
+            Node::Group(child) => walk(child, start, regions),
+            Node::Token(token) => {
+                if token.is_word("row") {
+                    *start = Some(token.end);
+                } else if token.is_word("trowd") || token.is_word("intbl") {
+                    *start = None;
+                } else if token.is_word("page") || token.is_word("sect") {
+                    if let Some(start) = start.take() {
+                        regions.push((start, token.start));
+                    }
+                }
+            }
         }
-    } else {
-        None
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::rtf::tree;
+
     use super::*;
     #[test]
-    fn footnote_position_test() {
-        let source = br"\f2\fs16 \par \uc0\u26469 \uc0\u28304 \uc0\u65306  ..\\ak112\\303\\stats\\csr\\product\\program\\tfl\\t-14-02-02-01-os-fas.sas Zhengkun.Liang SAS9.4\uc0\u65288 DCO \uc0\u26085 \uc0\u26399 \uc0\u65306 29JAN2024\uc0\u65292 \uc0\u36755 \uc0\u20986 \uc0\u26085 \uc0\u26399 \uc0\u65306 29MAY2024 15:26\uc0\u65289 \uc1\cf0\chcbpat0
+    fn footnote_regions_test() {
+        let source = br"{\rtf1{\header\trowd\cellx100\intbl{Title\cell}\row}
+\trowd\cellx100\pard\plain\intbl\f1{Male\cell}{\row}
+\pard\plain\ql\f3\fs18 \par Note A\cf0
 {\par}{\pard\plain\qc{
 }\par}{\page\par}
-\trowd\trkeep\trqc
-\cltxlrtb\clvertalt\clcbpat20\cellx6977
-\cltxlrtb\clvertalt\clcbpat20\cellx13954
-\pard\plain\intbl\sb0\sa0\ql\f1\f2\f2\fs21\cf21";
-        let result = footnote_position(source, (0, source.len()));
-        assert!(result.is_some());
+\trowd\cellx100\pard\plain\intbl\f1{Female\cell}\row
+Between tables\par
+\trowd\cellx100\pard\plain\intbl\f1{Total\cell}\row
+\f3 Note B}";
+        let root = tree::parse(source).unwrap();
+        let regions = footnote_regions(&root)
+            .into_iter()
+            .map(|(start, end)| String::from_utf8_lossy(&source[start..end]).to_string())
+            .collect::<Vec<String>>();
+        // text between tables on the same page is not footnote
+        assert_eq!(2, regions.len());
+        assert!(regions[0].contains("Note A") && regions[0].ends_with(r"\par}{"));
+        assert_eq!("\n\\f3 Note B}", regions[1]);
     }
 }
@@ -0,0 +1,433 @@
+use chrono::{NaiveDate, NaiveDateTime};
+
+use super::{
+    error::SplitError,
+    lexer::{Token, TokenKind},
+    tree::{self, Group, Node},
+};
+
+/// tables and document information before contents of rtf
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Header {
+    /// \ansicpgN, code page of \'hh escapes, such as 936 for chinese
+    pub code_page: Option<u32>,
+    /// \deffN, font used when no font is selected
+    pub default_font: Option<i32>,
+    pub fonts: Vec<Font>,
+    /// colors referred by \cfN and \cbN, none is the auto color which is usually the first one
+    pub colors: Vec<Option<Color>>,
+    pub styles: Vec<Style>,
+    pub info: Info,
+}
+
+/// an entry of font table, such as {\f1\froman\fprq2\fcharset134 SimSun;}
+#[derive(Debug, Clone, PartialEq)]
+pub struct Font {
+    /// N of \fN
+    pub code: i32,
+    pub family: Option<FontFamily>,
+    pub charset: Option<u32>,
+    /// \fprqN, 0 for default, 1 for fixed and 2 for variable pitch
+    pub pitch: Option<u32>,
+    /// name of font, escapes such as \'cb are kept as they are
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontFamily {
+    Nil,
+    Roman,
+    Swiss,
+    Modern,
+    Script,
+    Decor,
+    Tech,
+    Bidi,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+/// an entry of stylesheet, such as {\s1\ql\sbasedon0\snext1 heading 1;}
+#[derive(Debug, Clone, PartialEq)]
+pub struct Style {
+    pub kind: StyleKind,
+    /// N of \sN, \csN, \dsN or \tsN, 0 for the normal paragraph style without it
+    pub code: i32,
+    pub name: String,
+    pub based_on: Option<i32>,
+    pub next: Option<i32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StyleKind {
+    Paragraph,
+    Character,
+    Section,
+    Table,
+}
+
+/// the \info group, texts keep escapes such as \'cb as they are
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Info {
+    pub title: Option<String>,
+    pub subject: Option<String>,
+    pub author: Option<String>,
+    pub operator: Option<String>,
+    pub keywords: Option<String>,
+    pub comment: Option<String>,
+    pub company: Option<String>,
+    pub created: Option<NaiveDateTime>,
+    pub revised: Option<NaiveDateTime>,
+    pub printed: Option<NaiveDateTime>,
+}
+
+impl Header {
+    /// parse header of rtf, tables missing in rtf are left empty
+    pub fn parse(bytes: &[u8]) -> Result<Header, SplitError> {
+        Ok(Header::from_tree(&tree::parse(bytes)?, bytes))
+    }
+
+    pub(crate) fn from_tree(root: &Group, bytes: &[u8]) -> Header {
+        let mut header = Header::default();
+        if let Some(document) = root.groups().next() {
+            for token in tokens(&document.children) {
+                match &token.kind {
+                    TokenKind::ControlWord { name, param } if name == "ansicpg" => {
+                        header.code_page = param.map(|param| param as u32)
+                    }
+                    TokenKind::ControlWord { name, param } if name == "deff" => {
+                        header.default_font = *param
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if let Some(fonttbl) = root.find("fonttbl") {
+            header.fonts = match fonttbl.groups().count() {
+                // a table with only one font may not enclose it in a group
+                0 => Font::parse(&fonttbl.children, bytes).into_iter().collect(),
+                _ => fonttbl
+                    .groups()
+                    .filter_map(|font| Font::parse(&font.children, bytes))
+                    .collect(),
+            };
+        }
+        if let Some(colortbl) = root.find("colortbl") {
+            header.colors = colors(colortbl, bytes);
+        }
+        if let Some(stylesheet) = root.find("stylesheet") {
+            header.styles = stylesheet
+                .groups()
+                .filter_map(|style| Style::parse(style, bytes))
+                .collect();
+        }
+        if let Some(info) = root.find("info") {
+            header.info = Info::parse(info, bytes);
+        }
+        header
+    }
+
+    /// font selected by \fN
+    pub fn font(&self, code: i32) -> Option<&Font> {
+        self.fonts.iter().find(|font| font.code == code)
+    }
+}
+
+impl Font {
+    pub(crate) fn parse(nodes: &[Node], bytes: &[u8]) -> Option<Font> {
+        let mut code = None;
+        let mut family = None;
+        let mut charset = None;
+        let mut pitch = None;
+        for token in tokens(nodes) {
+            if let TokenKind::ControlWord { name, param } = &token.kind {
+                match (name.as_str(), param) {
+                    ("f", Some(param)) => code = code.or(Some(*param)),
+                    ("fcharset", Some(param)) => charset = Some(*param as u32),
+                    ("fprq", Some(param)) => pitch = Some(*param as u32),
+                    (name, None) => family = family.or(FontFamily::from_word(name)),
+                    _ => {}
+                }
+            }
+        }
+        Some(Font {
+            code: code?,
+            family,
+            charset,
+            pitch,
+            name: name(nodes, bytes),
+        })
+    }
+
+    /// entry of font table, for example: {\f1\froman\fprq2\fcharset0 Times New Roman;}
+    pub fn to_rtf(&self) -> String {
+        let mut entry = format!(r"{{\f{}", self.code);
+        if let Some(family) = self.family {
+            entry.push_str(&format!(r"\{}", family.word()));
+        }
+        if let Some(pitch) = self.pitch {
+            entry.push_str(&format!(r"\fprq{}", pitch));
+        }
+        if let Some(charset) = self.charset {
+            entry.push_str(&format!(r"\fcharset{}", charset));
+        }
+        format!("{} {};}}", entry, self.name)
+    }
+}
+
+impl FontFamily {
+    fn from_word(word: &str) -> Option<FontFamily> {
+        match word {
+            "fnil" => Some(FontFamily::Nil),
+            "froman" => Some(FontFamily::Roman),
+            "fswiss" => Some(FontFamily::Swiss),
+            "fmodern" => Some(FontFamily::Modern),
+            "fscript" => Some(FontFamily::Script),
+            "fdecor" => Some(FontFamily::Decor),
+            "ftech" => Some(FontFamily::Tech),
+            "fbidi" => Some(FontFamily::Bidi),
+            _ => None,
+        }
+    }
+
+    pub fn word(&self) -> &str {
+        match self {
+            FontFamily::Nil => "fnil",
+            FontFamily::Roman => "froman",
+            FontFamily::Swiss => "fswiss",
+            FontFamily::Modern => "fmodern",
+            FontFamily::Script => "fscript",
+            FontFamily::Decor => "fdecor",
+            FontFamily::Tech => "ftech",
+            FontFamily::Bidi => "fbidi",
+        }
+    }
+}
+
+impl Style {
+    fn parse(group: &Group, bytes: &[u8]) -> Option<Style> {
+        let mut kind = StyleKind::Paragraph;
+        let mut code = 0;
+        let mut based_on = None;
+        let mut next = None;
+        for token in tokens(&group.children) {
+            if let TokenKind::ControlWord {
+                name,
+                param: Some(param),
+            } = &token.kind
+            {
+                match name.as_str() {
+                    "s" => code = *param,
+                    "cs" => (kind, code) = (StyleKind::Character, *param),
+                    "ds" => (kind, code) = (StyleKind::Section, *param),
+                    "ts" => (kind, code) = (StyleKind::Table, *param),
+                    "sbasedon" => based_on = Some(*param),
+                    "snext" => next = Some(*param),
+                    _ => {}
+                }
+            }
+        }
+        let name = name(&group.children, bytes);
+        if name.is_empty() {
+            return None;
+        }
+        Some(Style {
+            kind,
+            code,
+            name,
+            based_on,
+            next,
+        })
+    }
+}
+
+impl Info {
+    fn parse(info: &Group, bytes: &[u8]) -> Info {
+        let mut result = Info::default();
+        for group in info.groups() {
+            let text = Some(text(&group.children, bytes).trim().to_string())
+                .filter(|text| !text.is_empty());
+            match group.name() {
+                Some("title") => result.title = text,
+                Some("subject") => result.subject = text,
+                Some("author") => result.author = text,
+                Some("operator") => result.operator = text,
+                Some("keywords") => result.keywords = text,
+                Some("doccomm") => result.comment = text,
+                Some("company") => result.company = text,
+                Some("creatim") => result.created = time(group),
+                Some("revtim") => result.revised = time(group),
+                Some("printim") => result.printed = time(group),
+                _ => {}
+            }
+        }
+        result
+    }
+}
+
+/// colors separated by semicolons, for example: ;\red0\green0\blue0; => [None, Some(black)]
+fn colors(colortbl: &Group, bytes: &[u8]) -> Vec<Option<Color>> {
+    let mut colors = vec![];
+    let mut color: Option<Color> = None;
+    for token in tokens(&colortbl.children) {
+        match &token.kind {
+            TokenKind::ControlWord {
+                name,
+                param: Some(param),
+            } => {
+                let value = (*param).clamp(0, 255) as u8;
+                let current = color.get_or_insert(Color {
+                    red: 0,
+                    green: 0,
+                    blue: 0,
+                });
+                match name.as_str() {
+                    "red" => current.red = value,
+                    "green" => current.green = value,
+                    "blue" => current.blue = value,
+                    _ => {}
+                }
+            }
+            TokenKind::Text => {
+                for c in &bytes[token.start..token.end] {
+                    if *c == b';' {
+                        colors.push(color.take());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    colors
+}
+
+/// date and time of a group such as {\creatim\yr2024\mo5\dy29\hr15\min26}
+fn time(group: &Group) -> Option<NaiveDateTime> {
+    let value = |word: &str| {
+        tokens(&group.children).find_map(|token| match &token.kind {
+            TokenKind::ControlWord { name, param } if name == word => *param,
+            _ => None,
+        })
+    };
+    let number = |word: &str| value(word).map(|value| value.max(0) as u32);
+    NaiveDate::from_ymd_opt(value("yr")?, number("mo")?, number("dy")?)?.and_hms_opt(
+        number("hr").unwrap_or_default(),
+        number("min").unwrap_or_default(),
+        number("sec").unwrap_or_default(),
+    )
+}
+
+/// tokens directly in nodes, tokens in nested groups are skipped
+fn tokens(nodes: &[Node]) -> impl Iterator<Item = &Token> {
+    nodes.iter().filter_map(|node| match node {
+        Node::Token(token) => Some(token),
+        Node::Group(_) => None,
+    })
+}
+
+/// visible text directly in nodes, escapes such as \'cb are kept as they are
+fn text(nodes: &[Node], bytes: &[u8]) -> String {
+    tokens(nodes)
+        .filter(|token| token.is_visible(bytes))
+        .map(|token| String::from_utf8_lossy(&bytes[token.start..token.end]))
+        .collect()
+}
+
+/// name of an entry in table, which ends with a semicolon
+fn name(nodes: &[Node], bytes: &[u8]) -> String {
+    let text = text(nodes, bytes);
+    text.split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+    #[test]
+    fn test_fonts() {
+        let filepath = Path::new(r"D:\projects\rusty\playground\rtf\.sample_data\cn.rtf");
+        let bytes = fs::read(filepath).unwrap();
+        let header = Header::parse(&bytes).unwrap();
+        assert_eq!(3, header.fonts.len());
+        let filepath = Path::new(r"D:\projects\rusty\playground\rtf\.sample_data\en.rtf");
+        let bytes = fs::read(filepath).unwrap();
+        let header = Header::parse(&bytes).unwrap();
+        assert_eq!(1, header.fonts.len());
+    }
+
+    #[test]
+    fn test_font_code() {
+        let source = br"{\fonttbl{\f1\froman\fprq2\fcharset0 SimSun;}}";
+        let header = Header::parse(source).unwrap();
+        assert_eq!(1, header.fonts[0].code);
+    }
+
+    #[test]
+    fn header_test() {
+        let source = br"{\rtf1\ansi\ansicpg936\deff0
+{\fonttbl{\f0\froman\fprq2\fcharset0 Times New Roman;}{\f1\fnil\fcharset134 \'cb\'ce\'cc\'e5{\*\falt SimSun};}}
+{\colortbl;\red0\green0\blue0;\red255\green255\blue255;}
+{\stylesheet{\ql\f0 Normal;}{\s1\ql\sbasedon0\snext1 heading 1;}{\*\cs10\additive Default Paragraph Font;}}
+{\info{\title t-14-01}{\author Zhengkun.Liang}{\creatim\yr2024\mo5\dy29\hr15\min26}}
+\widowctrl\f1{Male\cell}}";
+        let header = Header::parse(source).unwrap();
+        assert_eq!(Some(936), header.code_page);
+        assert_eq!(Some(0), header.default_font);
+        assert_eq!(
+            Some(&Font {
+                code: 1,
+                family: Some(FontFamily::Nil),
+                charset: Some(134),
+                pitch: None,
+                name: r"\'cb\'ce\'cc\'e5".into(),
+            }),
+            header.font(1)
+        );
+        assert_eq!(
+            r"{\f0\froman\fprq2\fcharset0 Times New Roman;}",
+            header.fonts[0].to_rtf()
+        );
+        assert_eq!(
+            vec![
+                None,
+                Some(Color {
+                    red: 0,
+                    green: 0,
+                    blue: 0
+                }),
+                Some(Color {
+                    red: 255,
+                    green: 255,
+                    blue: 255
+                }),
+            ],
+            header.colors
+        );
+        assert_eq!(3, header.styles.len());
+        assert_eq!(
+            (StyleKind::Paragraph, 0),
+            (header.styles[0].kind, header.styles[0].code)
+        );
+        assert_eq!(Some(0), header.styles[1].based_on);
+        assert_eq!(StyleKind::Character, header.styles[2].kind);
+        assert_eq!("Default Paragraph Font", header.styles[2].name);
+        assert_eq!(Some("t-14-01".to_string()), header.info.title);
+        assert_eq!(Some("Zhengkun.Liang".to_string()), header.info.author);
+        assert_eq!(
+            NaiveDate::from_ymd_opt(2024, 5, 29)
+                .unwrap()
+                .and_hms_opt(15, 26, 0),
+            header.info.created
+        );
+    }
+}
@@ -0,0 +1,578 @@
+use crate::utils::Language;
+
+use super::{
+    error::SplitError,
+    header::Header,
+    lexer::{Token, TokenKind},
+    tree::{self, Group, Node},
+};
+
+/// letter size paper with 1.25 inches margins, in twips
+const DEFAULT_PAPER_WIDTH: i32 = 12240;
+const DEFAULT_MARGIN: i32 = 1800;
+/// \fs24, 12 points
+const DEFAULT_FONT_SIZE: i32 = 24;
+/// widths of printable ascii characters of Times New Roman, in 1/1000 em
+const TIMES_WIDTHS: [i32; 95] = [
+    250, 333, 408, 500, 500, 833, 778, 180, 333, 333, 500, 564, 250, 333, 250,
+    278, // ' ' to /
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500, // 0 to 9
+    278, 278, 564, 564, 564, 444, 921, // : to @
+    722, 667, 667, 722, 611, 556, 722, 722, 333, 389, 722, 611, 889, 722, 722, 556, 722, 667, 556,
+    611, 722, 722, 944, 722, 722, 611, // A to Z
+    333, 278, 333, 469, 500, 333, // [ to `
+    444, 500, 444, 500, 444, 333, 500, 500, 278, 278, 500, 278, 778, 500, 500, 500, 500, 333, 389,
+    278, 500, 500, 722, 500, 500, 444, // a to z
+    480, 200, 480, 541, // { to ~
+];
+/// width of characters in fixed pitch fonts, such as Courier New
+const FIXED_WIDTH: i32 = 600;
+const WIDE_WIDTH: i32 = 1000;
+const OTHER_WIDTH: i32 = 500;
+
+/// a cell whose longest word is still wider than its column after reflow, widths are in twips
+#[derive(Debug, Clone, PartialEq)]
+pub struct Overflow {
+    pub text: String,
+    pub required: i32,
+    pub available: i32,
+}
+
+/// widen columns of tables whose translated text is wider than them, within the page width
+///
+/// consecutive rows are regarded as one table, whose columns are edges of cells in all rows, so
+/// that a cell spanning several columns is kept aligned with the others. columns are given widths
+/// by the widest text, the longest word of a cell must fit in one line while other text may be
+/// wrapped. rows of exact height are changed to rows of minimum height when text in them is
+/// wrapped. return the rewritten rtf and cells which still can not fit
+pub fn reflow(bytes: &[u8]) -> Result<(Vec<u8>, Vec<Overflow>), SplitError> {
+    let root = tree::parse(bytes)?;
+    let header = Header::from_tree(&root, bytes);
+    let mut measurer = Measurer::new(bytes, &header);
+    measurer.visit(&root);
+    let page_width = page_width(&root);
+    let mut replacements: Vec<(usize, usize, String)> = vec![];
+    let mut overflows = vec![];
+    for table in measurer.tables.iter() {
+        layout(table, bytes, page_width, &mut replacements, &mut overflows);
+    }
+
+    replacements.sort_by_key(|(start, _, _)| *start);
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut cursor = 0;
+    for (start, end, content) in replacements {
+        result.extend_from_slice(&bytes[cursor..start]);
+        result.extend_from_slice(content.as_bytes());
+        cursor = end;
+    }
+    result.extend_from_slice(&bytes[cursor..]);
+    Ok((result, overflows))
+}
+
+/// rewrite \cellx and \trleft of rows in a table by new widths of its columns
+fn layout(
+    rows: &[Row],
+    bytes: &[u8],
+    page_width: i32,
+    replacements: &mut Vec<(usize, usize, String)>,
+    overflows: &mut Vec<Overflow>,
+) {
+    let mut edges = rows
+        .iter()
+        .flat_map(|row| row.edges())
+        .collect::<Vec<i32>>();
+    edges.sort();
+    edges.dedup();
+    if edges.len() < 2 {
+        return;
+    }
+    let widths = edges
+        .windows(2)
+        .map(|edge| edge[1] - edge[0])
+        .collect::<Vec<i32>>();
+    let column = |edge: i32| edges.binary_search(&edge).unwrap_or_default();
+
+    // cells in one column go first, so that spanning cells only widen columns when required
+    let mut cells = rows
+        .iter()
+        .flat_map(|row| {
+            row.spans()
+                .into_iter()
+                .zip(row.cells.iter())
+                .map(move |((left, right), cell)| (column(left), column(right), row.gap, cell))
+        })
+        .filter(|(left, right, _, _)| left < right)
+        .collect::<Vec<(usize, usize, i32, &Cell)>>();
+    cells.sort_by_key(|(left, right, _, _)| right - left);
+    let mut words = vec![0; widths.len()];
+    let mut lines = vec![0; widths.len()];
+    for (left, right, gap, cell) in cells.iter() {
+        let widths = &widths[*left..*right];
+        widen(
+            &mut words[*left..*right],
+            widths,
+            cell.longest_word + 2 * gap,
+        );
+        widen(
+            &mut lines[*left..*right],
+            widths,
+            cell.longest_line + 2 * gap,
+        );
+    }
+
+    let widths = allocate(&widths, &words, &lines, page_width - edges[0]);
+    let mut new_edges = vec![edges[0]];
+    for width in widths {
+        new_edges.push(new_edges[new_edges.len() - 1] + width);
+    }
+    let new_edge = |edge: i32| new_edges[column(edge)];
+    for row in rows {
+        if let Some((start, end)) = row.left_token {
+            replacements.push((
+                start,
+                end,
+                control(bytes, end, "trleft", new_edge(row.left)),
+            ));
+        }
+        let mut wrapped = false;
+        for (((start, end, boundary), (left, right)), cell) in
+            row.boundaries.iter().zip(row.spans()).zip(row.cells.iter())
+        {
+            replacements.push((
+                *start,
+                *end,
+                control(bytes, *end, "cellx", new_edge(*boundary)),
+            ));
+            let available = new_edge(right) - new_edge(left) - 2 * row.gap;
+            if cell.longest_word > available {
+                overflows.push(Overflow {
+                    text: cell.text.trim().to_string(),
+                    required: cell.longest_word,
+                    available,
+                });
+            }
+            wrapped |= cell.longest_line > available;
+        }
+        if let Some((start, end, height)) = row.height {
+            if height < 0 && wrapped {
+                replacements.push((start, end, control(bytes, end, "trrh", -height)));
+            }
+        }
+    }
+}
+
+/// widen columns a cell spans when they are narrower than it requires, in proportion to their
+/// widths, for example: required [100, 300], widths [1000, 3000], 800 => [200, 600]
+fn widen(required: &mut [i32], widths: &[i32], width: i32) {
+    let extra = width - required.iter().sum::<i32>();
+    if extra <= 0 {
+        return;
+    }
+    let total = widths.iter().sum::<i32>().max(1);
+    let mut rest = extra;
+    for (required, column) in required.iter_mut().zip(widths.iter()) {
+        let share = (extra as i64 * *column as i64 / total as i64) as i32;
+        *required += share;
+        rest -= share;
+    }
+    if let Some(last) = required.last_mut() {
+        *last += rest;
+    }
+}
+
+/// widths of columns, which are kept if text fits in them already
+///
+/// columns are widened to fit their text in one line when page is wide enough. otherwise every
+/// column is given the width of its longest word first, and the rest of page is shared by columns
+/// according to how much wider their text is, so a column whose text is short may be narrowed
+/// to give room to the others. for example: widths [1000, 1000], words [500, 1500], lines [1000,
+/// 3500], page 3000 => [700, 2300]
+fn allocate(widths: &[i32], words: &[i32], lines: &[i32], page_width: i32) -> Vec<i32> {
+    if lines
+        .iter()
+        .zip(widths.iter())
+        .all(|(line, width)| line <= width)
+    {
+        return widths.to_vec();
+    }
+    let lines = lines
+        .iter()
+        .zip(widths.iter())
+        .map(|(line, width)| *line.max(width))
+        .collect::<Vec<i32>>();
+    let current = widths.iter().sum::<i32>();
+    let (words_sum, lines_sum) = (words.iter().sum::<i32>(), lines.iter().sum::<i32>());
+    // the table grows to fit text in one line, but no wider than page
+    let total = lines_sum.min(page_width).max(current);
+    let mut result = if words_sum >= total {
+        words
+            .iter()
+            .map(|word| (*word as i64 * total as i64 / words_sum as i64) as i32)
+            .collect::<Vec<i32>>()
+    } else {
+        let (extra, wanted) = (total - words_sum, lines_sum - words_sum);
+        words
+            .iter()
+            .zip(lines.iter())
+            .map(|(word, line)| word + ((line - word) as i64 * extra as i64 / wanted as i64) as i32)
+            .collect::<Vec<i32>>()
+    };
+    // give the rest of rounding to the last column
+    let rest = total - result.iter().sum::<i32>();
+    if let Some(last) = result.last_mut() {
+        *last += rest;
+    }
+    result
+}
+
+/// rewrite a control word with a new parameter, the space delimiting it is kept
+fn control(bytes: &[u8], end: usize, name: &str, param: i32) -> String {
+    let delimiter = if bytes[end - 1] == b' ' { " " } else { "" };
+    format!(r"\{}{}{}", name, param, delimiter)
+}
+
+/// width of page without margins, in twips
+fn page_width(root: &Group) -> i32 {
+    let value = |word: &str| {
+        root.groups().next().and_then(|document| {
+            document.children.iter().find_map(|node| match node {
+                Node::Token(Token {
+                    kind: TokenKind::ControlWord { name, param },
+                    ..
+                }) if name == word => *param,
+                _ => None,
+            })
+        })
+    };
+    value("paperw").unwrap_or(DEFAULT_PAPER_WIDTH)
+        - value("margl").unwrap_or(DEFAULT_MARGIN)
+        - value("margr").unwrap_or(DEFAULT_MARGIN)
+}
+
+/// text of a cell and widths of it in twips
+#[derive(Debug, Default)]
+struct Cell {
+    text: String,
+    line: i32,
+    word: i32,
+    longest_line: i32,
+    longest_word: i32,
+}
+
+impl Cell {
+    fn push(&mut self, c: char, width: i32, breakable: bool) {
+        self.text.push(c);
+        self.line += width;
+        if breakable {
+            self.end_word();
+        } else {
+            self.word += width;
+        }
+    }
+
+    fn end_word(&mut self) {
+        self.longest_word = self.longest_word.max(self.word);
+        self.word = 0;
+    }
+
+    fn end_line(&mut self) {
+        self.end_word();
+        self.longest_line = self.longest_line.max(self.line);
+        self.line = 0;
+        self.text.push(' ');
+    }
+}
+
+/// a table row from \trowd to \row, positions of control words are kept for rewriting
+#[derive(Debug, Default)]
+struct Row {
+    /// \trleftN
+    left: i32,
+    left_token: Option<(usize, usize)>,
+    /// \trgaphN, half of space between cells
+    gap: i32,
+    /// \trrhN, negative for exact height
+    height: Option<(usize, usize, i32)>,
+    /// \cellxN, right boundaries of cells
+    boundaries: Vec<(usize, usize, i32)>,
+    cells: Vec<Cell>,
+}
+
+impl Row {
+    /// left and right edges of cells
+    fn edges(&self) -> Vec<i32> {
+        let mut edges = vec![self.left];
+        edges.extend(self.boundaries.iter().map(|(_, _, boundary)| *boundary));
+        edges
+    }
+
+    /// left and right edges of each cell
+    fn spans(&self) -> Vec<(i32, i32)> {
+        self.edges()
+            .windows(2)
+            .map(|edge| (edge[0], edge[1]))
+            .collect()
+    }
+}
+
+/// walk through rtf and measure text of table cells
+struct Measurer<'a> {
+    bytes: &'a [u8],
+    header: &'a Header,
+    font: Option<i32>,
+    /// in half points
+    size: i32,
+    /// \ucN and fallback characters left to skip
+    skip_count: usize,
+    skip: usize,
+    /// whether current paragraph is in a table, text out of tables is not measured
+    in_table: bool,
+    row: Row,
+    cell: Cell,
+    /// consecutive rows, which are separated by text out of tables and page breaks
+    tables: Vec<Vec<Row>>,
+    separated: bool,
+}
+
+impl<'a> Measurer<'a> {
+    fn new(bytes: &'a [u8], header: &'a Header) -> Self {
+        Measurer {
+            bytes,
+            header,
+            font: header.default_font,
+            size: DEFAULT_FONT_SIZE,
+            skip_count: 1,
+            skip: 0,
+            in_table: false,
+            row: Row::default(),
+            cell: Cell::default(),
+            tables: vec![],
+            separated: true,
+        }
+    }
+
+    fn visit(&mut self, group: &Group) {
+        for node in group.children.iter() {
+            match node {
+                Node::Group(child) if !child.is_hidden() => {
+                    let state = (self.font, self.size, self.skip_count, self.in_table);
+                    self.skip = 0;
+                    self.visit(child);
+                    (self.font, self.size, self.skip_count, self.in_table) = state;
+                    self.skip = 0;
+                }
+                Node::Group(_) => {}
+                Node::Token(token) => self.token(token),
+            }
+        }
+    }
+
+    fn token(&mut self, token: &Token) {
+        if self.skip > 0 {
+            match &token.kind {
+                TokenKind::Text => {
+                    let text = String::from_utf8_lossy(&self.bytes[token.start..token.end]);
+                    for c in text.chars() {
+                        if self.skip > 0 {
+                            self.skip -= 1;
+                        } else {
+                            self.char(c);
+                        }
+                    }
+                }
+                _ => self.skip -= 1,
+            }
+            return;
+        }
+        match &token.kind {
+            TokenKind::ControlWord { name, param } => {
+                let value = param.unwrap_or_default();
+                match name.as_str() {
+                    "trowd" => {
+                        self.row = Row::default();
+                        self.cell = Cell::default();
+                        self.in_table = true;
+                    }
+                    "intbl" => self.in_table = true,
+                    "pard" => self.in_table = false,
+                    "page" | "sect" => self.separated = true,
+                    "trleft" => {
+                        self.row.left = value;
+                        self.row.left_token = Some((token.start, token.end));
+                    }
+                    "trgaph" => self.row.gap = value,
+                    "trrh" => self.row.height = Some((token.start, token.end, value)),
+                    "cellx" => self.row.boundaries.push((token.start, token.end, value)),
+                    "plain" => {
+                        self.font = self.header.default_font;
+                        self.size = DEFAULT_FONT_SIZE;
+                    }
+                    "f" => self.font = *param,
+                    "fs" => self.size = value,
+                    "line" | "par" if self.in_table => self.cell.end_line(),
+                    "tab" => self.char(' '),
+                    "cell" => {
+                        let mut cell = std::mem::take(&mut self.cell);
+                        cell.end_line();
+                        self.row.cells.push(cell);
+                    }
+                    "row" => {
+                        let row = std::mem::take(&mut self.row);
+                        self.cell = Cell::default();
+                        self.in_table = false;
+                        if row.cells.is_empty() || row.cells.len() != row.boundaries.len() {
+                            return;
+                        }
+                        match self.tables.last_mut() {
+                            Some(table) if !self.separated => table.push(row),
+                            _ => self.tables.push(vec![row]),
+                        }
+                        self.separated = false;
+                    }
+                    "uc" => self.skip_count = value.max(0) as usize,
+                    "u" => {
+                        match value as u16 {
+                            // a character beyond BMP, which is measured by its high surrogate
+                            0xD800..=0xDBFF => self.char('\u{20000}'),
+                            0xDC00..=0xDFFF => {}
+                            unit => self.char(char::from_u32(unit as u32).unwrap_or(' ')),
+                        }
+                        self.skip = self.skip_count;
+                    }
+                    _ => {}
+                }
+            }
+            TokenKind::ControlSymbol(c) => match c {
+                b'\\' | b'{' | b'}' => self.char(*c as char),
+                b'~' => self.char('\u{a0}'),
+                b'_' => self.char('-'),
+                _ => {}
+            },
+            // a byte of a character in code page, a double bytes character is as wide as 2 bytes
+            TokenKind::Hex(value) if *value >= 0x80 => {
+                if !self.in_table {
+                    self.separated = true;
+                    return;
+                }
+                let width = self.width(OTHER_WIDTH);
+                self.cell.push('\u{fffd}', width, false);
+            }
+            TokenKind::Hex(value) => self.char(*value as char),
+            TokenKind::Text => {
+                let text = String::from_utf8_lossy(&self.bytes[token.start..token.end]);
+                for c in text.chars().filter(|c| !matches!(c, '\r' | '\n')) {
+                    self.char(c);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn char(&mut self, c: char) {
+        if !self.in_table {
+            self.separated |= !c.is_whitespace();
+            return;
+        }
+        let fixed = self
+            .font
+            .and_then(|font| self.header.font(font))
+            .is_some_and(|font| font.pitch == Some(1));
+        let wide = Language::Japanese.detect(c.encode_utf8(&mut [0; 4]));
+        let units = match c {
+            _ if wide => WIDE_WIDTH,
+            _ if fixed => FIXED_WIDTH,
+            ' '..='~' => TIMES_WIDTHS[c as usize - ' ' as usize],
+            _ => OTHER_WIDTH,
+        };
+        let width = self.width(units);
+        // lines break at spaces and between wide characters
+        match c {
+            ' ' => {
+                self.cell.end_word();
+                self.cell.push(c, width, true);
+            }
+            _ if wide => {
+                self.cell.end_word();
+                self.cell.push(c, width, true);
+            }
+            _ => self.cell.push(c, width, false),
+        }
+    }
+
+    /// width of 1/1000 em in current font size, in twips
+    fn width(&self, units: i32) -> i32 {
+        // a point is 20 twips, and font size is in half points
+        units * self.size * 10 / 1000
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocate_test() {
+        assert_eq!(
+            vec![700, 2300],
+            allocate(&[1000, 1000], &[500, 1500], &[1000, 3500], 3000)
+        );
+        // columns are widened to fit text in one line, and never narrowed
+        assert_eq!(
+            vec![1000, 3000],
+            allocate(&[1000, 1000], &[500, 1500], &[900, 3000], 6000)
+        );
+        // text fits already
+        assert_eq!(
+            vec![1000, 1000],
+            allocate(&[1000, 1000], &[800, 500], &[900, 1000], 4000)
+        );
+        // longest words are wider than page
+        assert_eq!(
+            vec![1000, 3000],
+            allocate(&[1000, 1000], &[1000, 3000], &[1000, 3000], 4000)
+        );
+    }
+
+    #[test]
+    fn widen_test() {
+        let mut required = [100, 300];
+        widen(&mut required, &[1000, 3000], 800);
+        assert_eq!([200, 600], required);
+        widen(&mut required, &[1000, 3000], 500);
+        assert_eq!([200, 600], required);
+    }
+
+    #[test]
+    fn reflow_test() {
+        let source = br"{\rtf1\ansi\deff0\paperw4000\margl500\margr500{\fonttbl{\f0\froman Times New Roman;}}
+\pard\plain\f0\fs20 Table 14.1.1 Summary of Demographic and Baseline Characteristics\par
+\trowd\trgaph100\trrh-300\cellx1000\cellx2000\pard\plain\intbl\f0\fs20{Subject\cell}{Treatment Emergent Adverse Events\cell}\row
+\trowd\trgaph100\trrh-300\cellx1000\cellx2000\pard\plain\intbl\f0\fs20{Age\cell}{12\cell}\row
+\pard\plain\f0\fs20 Note: a footnote which is much longer than any column of the table\par
+\trowd\cellx1000\pard\plain\intbl\f0\fs20{Pneumonoultramicroscopicsilicovolcanoconiosis\cell}\row}";
+        let (result, overflows) = reflow(source).unwrap();
+        let result = String::from_utf8(result).unwrap();
+        // title and footnote are not measured as text of cells
+        assert!(result.contains(r"\trowd\trgaph100\trrh300\cellx900\cellx3000\pard"));
+        assert!(result.contains(r"\trowd\trgaph100\trrh-300\cellx900\cellx3000\pard"));
+        assert_eq!(1, overflows.len());
+        assert_eq!(
+            "Pneumonoultramicroscopicsilicovolcanoconiosis",
+            overflows[0].text
+        );
+        assert_eq!(3000, overflows[0].available);
+    }
+
+    #[test]
+    fn reflow_spanning_test() {
+        let source = br"{\rtf1\ansi\deff0{\fonttbl{\f0\froman Times New Roman;}}
+\trowd\trgaph100\cellx1000\cellx3000\pard\plain\intbl\f0\fs20{Group\cell}{Treatment Emergent Adverse Events of Special Interest\cell}\row
+\trowd\trgaph100\cellx1000\cellx2000\cellx3000\pard\plain\intbl\f0\fs20{Age\cell}{Placebo\cell}{Investigational Product\cell}\row}";
+        let (result, overflows) = reflow(source).unwrap();
+        let result = String::from_utf8(result).unwrap();
+        // the spanning header ends at the same edge as the last column of body
+        assert!(result.contains(r"\cellx1000\cellx5642\pard"));
+        assert!(result.contains(r"\cellx1000\cellx2710\cellx5642\pard"));
+        assert!(overflows.is_empty());
+    }
+}
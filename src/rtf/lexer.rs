@@ -0,0 +1,214 @@
+use super::{
+    error::{SplitError, SplitReason},
+    symbol::{LEFT_BRACE, RIGHT_BRACE, SLASH},
+};
+
+/// longest name of a control word allowed by rtf specification
+const MAX_NAME_LENGTH: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    GroupStart,
+    GroupEnd,
+    /// such as \fs16 and \uc1, the space delimiting it belongs to the token
+    ControlWord {
+        name: String,
+        param: Option<i32>,
+    },
+    /// a backslash followed by a character which is not a letter, such as \~, \* and \{
+    ControlSymbol(u8),
+    /// \'hh, a byte in the code page of document
+    Hex(u8),
+    /// \binN and the N bytes of data following it
+    Binary,
+    Text,
+}
+
+/// a token and its position in source, source[start..end] is the raw bytes of it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Token {
+    /// whether it is a control word with the name
+    pub fn is_word(&self, name: &str) -> bool {
+        matches!(&self.kind, TokenKind::ControlWord { name: n, .. } if n == name)
+    }
+
+    /// whether it shows something in document, such as text, \'hh, \uN and \~
+    pub fn is_visible(&self, bytes: &[u8]) -> bool {
+        match &self.kind {
+            TokenKind::Text => bytes[self.start..self.end]
+                .iter()
+                .any(|c| !c.is_ascii_whitespace()),
+            TokenKind::Hex(_) => true,
+            TokenKind::ControlWord { name, .. } => name == "u",
+            TokenKind::ControlSymbol(c) => !matches!(c, b'*' | b'\n' | b'\r'),
+            _ => false,
+        }
+    }
+}
+
+/// split rtf into tokens
+///
+/// for example: {\f1\fs16 Male\'a3\par} => GroupStart, \f1, \fs16 , Male, \'a3, \par, GroupEnd
+pub struct Lexer<'a> {
+    bytes: &'a [u8],
+    cursor: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Lexer { bytes, cursor: 0 }
+    }
+
+    fn control(&mut self) -> Result<Token, SplitError> {
+        let start = self.cursor;
+        let bytes = self.bytes;
+        let next = match bytes.get(start + 1) {
+            Some(c) => *c,
+            None => return Err(SplitError::new(start, SplitReason::TrailingBackslash)),
+        };
+        if next == b'\'' {
+            let hex = bytes
+                .get(start + 2..start + 4)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            return match hex {
+                Some(value) => Ok(self.token(TokenKind::Hex(value), start, start + 4)),
+                None => Err(SplitError::new(start, SplitReason::InvalidHex)),
+            };
+        }
+        if !next.is_ascii_alphabetic() {
+            return Ok(self.token(TokenKind::ControlSymbol(next), start, start + 2));
+        }
+        let mut end = start + 1;
+        while end < bytes.len() && bytes[end].is_ascii_alphabetic() {
+            end += 1;
+        }
+        if end - start - 1 > MAX_NAME_LENGTH {
+            return Err(SplitError::new(start, SplitReason::ControlWordTooLong));
+        }
+        let name = String::from_utf8_lossy(&bytes[start + 1..end]).to_string();
+        let param_start = end;
+        if bytes.get(end) == Some(&b'-') {
+            end += 1;
+        }
+        while end < bytes.len() && bytes[end].is_ascii_digit() {
+            end += 1;
+        }
+        let param = match &bytes[param_start..end] {
+            [] => None,
+            digits => match String::from_utf8_lossy(digits).parse::<i32>() {
+                Ok(param) => Some(param),
+                Err(_) => return Err(SplitError::new(start, SplitReason::InvalidParameter(name))),
+            },
+        };
+        if bytes.get(end) == Some(&b' ') {
+            end += 1;
+        }
+        if name == "bin" {
+            let size = param.unwrap_or_default().max(0) as usize;
+            if end + size > bytes.len() {
+                return Err(SplitError::new(start, SplitReason::BinaryOverflow(size)));
+            }
+            return Ok(self.token(TokenKind::Binary, start, end + size));
+        }
+        Ok(self.token(TokenKind::ControlWord { name, param }, start, end))
+    }
+
+    fn token(&mut self, kind: TokenKind, start: usize, end: usize) -> Token {
+        self.cursor = end;
+        Token { kind, start, end }
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Result<Token, SplitError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.cursor;
+        let c = *self.bytes.get(start)?;
+        let token = match c {
+            LEFT_BRACE => Ok(self.token(TokenKind::GroupStart, start, start + 1)),
+            RIGHT_BRACE => Ok(self.token(TokenKind::GroupEnd, start, start + 1)),
+            SLASH => self.control(),
+            _ => {
+                let end = self.bytes[start..]
+                    .iter()
+                    .position(|c| [LEFT_BRACE, RIGHT_BRACE, SLASH].contains(c))
+                    .map_or(self.bytes.len(), |offset| start + offset);
+                Ok(self.token(TokenKind::Text, start, end))
+            }
+        };
+        if token.is_err() {
+            // stop at the first error
+            self.cursor = self.bytes.len();
+        }
+        Some(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &[u8]) -> Vec<TokenKind> {
+        Lexer::new(source)
+            .map(|token| token.unwrap().kind)
+            .collect()
+    }
+
+    fn word(name: &str, param: Option<i32>) -> TokenKind {
+        TokenKind::ControlWord {
+            name: name.into(),
+            param,
+        }
+    }
+
+    #[test]
+    fn lexer_test() {
+        assert_eq!(
+            vec![
+                TokenKind::GroupStart,
+                word("f", Some(1)),
+                word("fs", Some(16)),
+                TokenKind::Text,
+                TokenKind::Hex(0xa3),
+                word("u", Some(-10187)),
+                TokenKind::ControlSymbol(b'{'),
+                TokenKind::ControlSymbol(b'*'),
+                word("par", None),
+                TokenKind::GroupEnd,
+            ],
+            kinds(br"{\f1\fs16 Male\'a3\u-10187\{\*\par}")
+        );
+        let tokens = Lexer::new(br"\fs16 Male")
+            .collect::<Result<Vec<Token>, SplitError>>()
+            .unwrap();
+        assert_eq!((0, 6), (tokens[0].start, tokens[0].end));
+        assert_eq!((6, 10), (tokens[1].start, tokens[1].end));
+        assert!(tokens[0].is_word("fs"));
+        assert!(!tokens[0].is_visible(br"\fs16 Male"));
+        assert!(tokens[1].is_visible(br"\fs16 Male"));
+    }
+
+    #[test]
+    fn binary_test() {
+        let source = b"{\\bin3 {}\\x}";
+        let tokens = Lexer::new(source)
+            .collect::<Result<Vec<Token>, SplitError>>()
+            .unwrap();
+        assert_eq!(TokenKind::Binary, tokens[1].kind);
+        assert_eq!(b"\\bin3 {}\\", &source[tokens[1].start..tokens[1].end]);
+        assert_eq!(TokenKind::Text, tokens[2].kind);
+        assert_eq!(
+            Some(SplitError::new(1, SplitReason::BinaryOverflow(9))),
+            Lexer::new(br" \bin9 ab").find_map(|token| token.err())
+        );
+        assert!(Lexer::new(br"\'zz").any(|token| token.is_err()));
+    }
+}
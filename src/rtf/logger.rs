@@ -27,10 +27,19 @@ impl Logger {
         })
     }
     pub fn write(&self, content: &str) -> anyhow::Result<()> {
+        self.append("INFO", content)
+    }
+
+    pub fn warn(&self, content: &str) -> anyhow::Result<()> {
+        self.append("WARN", content)
+    }
+
+    fn append(&self, level: &str, content: &str) -> anyhow::Result<()> {
         let mut writer = OpenOptions::new().append(true).open(&self.filepath)?;
         let content = format!(
-            "[{}][INFO] {}\n",
+            "[{}][{}] {}\n",
             chrono::Local::now().to_string().get(0..26).unwrap(),
+            level,
             content
         );
         writer.write(content.as_bytes())?;
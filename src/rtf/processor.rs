@@ -1,5 +1,7 @@
+mod decode;
+mod encode;
 mod misc;
-mod unicode;
 
+pub use decode::Decoder;
+pub use encode::{encode_unicode, escape_text};
 pub use misc::{depress_lf_cr, double_quote, percent, single_quote};
-pub use unicode::decode_unicode;
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+
+use encoding_rs::{
+    Encoding, BIG5, EUC_KR, GBK, MACINTOSH, SHIFT_JIS, UTF_8, WINDOWS_1250, WINDOWS_1251,
+    WINDOWS_1252, WINDOWS_1253, WINDOWS_1254, WINDOWS_1255, WINDOWS_1256, WINDOWS_1257,
+    WINDOWS_1258, WINDOWS_874,
+};
+
+use crate::rtf::{
+    header::Header,
+    lexer::{Lexer, TokenKind},
+};
+
+/// code page used when \ansicpg is missing
+const DEFAULT_CODE_PAGE: u32 = 1252;
+/// fallback characters following \uN when \ucN is missing
+const DEFAULT_SKIP: usize = 1;
+
+/// decode \uN and \'hh escapes into characters, other control words and groups are kept
+///
+/// whitespace out of ascii, such as the ideographic space of indents, is kept as a group of \uN
+/// without fallback characters, which is the same wherever \ucN it is restored in
+///
+/// \'hh are bytes in the code page of \fcharset of current font, or \ansicpg of document if the
+/// font uses ansi or default charset. for example, with \ansicpg936: \'cb\'ce\'cc\'e5 => 宋体
+#[derive(Debug, Clone)]
+pub struct Decoder {
+    code_page: u32,
+    default_font: Option<i32>,
+    /// code pages of fonts which are not the one of document
+    fonts: HashMap<i32, u32>,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder {
+            code_page: DEFAULT_CODE_PAGE,
+            default_font: None,
+            fonts: HashMap::new(),
+        }
+    }
+}
+
+impl Decoder {
+    pub fn new(header: &Header) -> Self {
+        Decoder {
+            code_page: header.code_page.unwrap_or(DEFAULT_CODE_PAGE),
+            default_font: header.default_font,
+            fonts: header
+                .fonts
+                .iter()
+                .filter_map(|font| Some((font.code, charset_code_page(font.charset?)?)))
+                .collect(),
+        }
+    }
+
+    /// decode a line of cell, font is the one selected before the cell
+    pub fn decode(&self, source: &str, font: Option<i32>) -> String {
+        let bytes = source.as_bytes();
+        let mut result = String::with_capacity(source.len());
+        let mut encoding = self.encoding(font);
+        let mut skip_count = DEFAULT_SKIP;
+        // encoding and \ucN of parent groups
+        let mut stack = vec![];
+        // \'hh bytes waiting for decoding, a character may be written in several bytes
+        let mut pending: Vec<u8> = vec![];
+        let mut high_surrogate: Option<u16> = None;
+        // fallback characters of the latest \uN left to skip
+        let mut skip = 0;
+        for token in Lexer::new(bytes) {
+            let token = match token {
+                Ok(token) => token,
+                Err(e) => {
+                    // keep the rest as it is, such as a trailing backslash
+                    flush(&mut result, &mut pending, encoding);
+                    result.push_str(&String::from_utf8_lossy(&bytes[e.offset..]));
+                    return result;
+                }
+            };
+            if !matches!(token.kind, TokenKind::Hex(_)) {
+                flush(&mut result, &mut pending, encoding);
+            }
+            // \binN may end in the middle of a character
+            let raw = String::from_utf8_lossy(&bytes[token.start..token.end]);
+            match &token.kind {
+                TokenKind::GroupStart | TokenKind::GroupEnd => skip = 0,
+                TokenKind::Text if skip > 0 => {
+                    let mut chars = raw.chars();
+                    while skip > 0 && chars.next().is_some() {
+                        skip -= 1;
+                    }
+                    result.push_str(chars.as_str());
+                    continue;
+                }
+                // a control word or symbol is regarded as one fallback character
+                _ if skip > 0 => {
+                    skip -= 1;
+                    continue;
+                }
+                _ => {}
+            }
+            // fallback characters of a high surrogate are skipped before the low one
+            if !token.is_word("u") {
+                high_surrogate = None;
+            }
+            match &token.kind {
+                TokenKind::GroupStart => {
+                    stack.push((encoding, skip_count));
+                    result.push_str(&raw);
+                }
+                TokenKind::GroupEnd => {
+                    if let Some(parent) = stack.pop() {
+                        (encoding, skip_count) = parent;
+                    }
+                    result.push_str(&raw);
+                }
+                TokenKind::Hex(value) => pending.push(*value),
+                TokenKind::ControlWord { name, param } => match (name.as_str(), param) {
+                    ("u", Some(param)) => {
+                        // \uN is a signed 16 bits integer, characters above 32767 are negative
+                        let unit = *param as u16;
+                        match (high_surrogate.take(), unit) {
+                            (None, 0xD800..=0xDBFF) => high_surrogate = Some(unit),
+                            (Some(high), 0xDC00..=0xDFFF) => {
+                                push_text(&mut result, &String::from_utf16_lossy(&[high, unit]))
+                            }
+                            _ => {
+                                let c = char::from_u32(unit as u32).unwrap_or('\u{FFFD}');
+                                if c.is_whitespace() && !c.is_ascii() {
+                                    // indents such as the ideographic space stay rtf, so that
+                                    // they are masked rather than translated or trimmed
+                                    result.push_str(&format!(r"{{\uc0\u{} }}", param));
+                                } else {
+                                    push_text(&mut result, &c.to_string())
+                                }
+                            }
+                        }
+                        skip = skip_count;
+                    }
+                    ("uc", param) => {
+                        skip_count = param.unwrap_or(DEFAULT_SKIP as i32).max(0) as usize
+                    }
+                    ("f", Some(param)) => {
+                        encoding = self.encoding(Some(*param));
+                        result.push_str(&raw);
+                    }
+                    _ => result.push_str(&raw),
+                },
+                _ => result.push_str(&raw),
+            }
+        }
+        flush(&mut result, &mut pending, encoding);
+        result
+    }
+
+    fn encoding(&self, font: Option<i32>) -> &'static Encoding {
+        let code_page = font
+            .or(self.default_font)
+            .and_then(|font| self.fonts.get(&font))
+            .copied()
+            .unwrap_or(self.code_page);
+        code_page_encoding(code_page)
+    }
+}
+
+/// decode pending bytes of \'hh into result
+fn flush(result: &mut String, pending: &mut Vec<u8>, encoding: &'static Encoding) {
+    if pending.is_empty() {
+        return;
+    }
+    let (text, _, _) = encoding.decode(pending);
+    push_text(result, &text);
+    pending.clear();
+}
+
+/// push decoded text, braces and backslashes are escaped so that they are still text
+fn push_text(result: &mut String, text: &str) {
+    for c in text.chars() {
+        if matches!(c, '{' | '}' | '\\') {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+}
+
+/// code page of \fcharsetN, none for ansi and default charset which follow \ansicpg
+fn charset_code_page(charset: u32) -> Option<u32> {
+    match charset {
+        77 => Some(10000),
+        128 => Some(932),
+        129 => Some(949),
+        134 => Some(936),
+        136 => Some(950),
+        161 => Some(1253),
+        162 => Some(1254),
+        163 => Some(1258),
+        177 => Some(1255),
+        178 => Some(1256),
+        186 => Some(1257),
+        204 => Some(1251),
+        222 => Some(874),
+        238 => Some(1250),
+        _ => None,
+    }
+}
+
+fn code_page_encoding(code_page: u32) -> &'static Encoding {
+    match code_page {
+        874 => WINDOWS_874,
+        932 => SHIFT_JIS,
+        936 => GBK,
+        949 => EUC_KR,
+        950 => BIG5,
+        1250 => WINDOWS_1250,
+        1251 => WINDOWS_1251,
+        1253 => WINDOWS_1253,
+        1254 => WINDOWS_1254,
+        1255 => WINDOWS_1255,
+        1256 => WINDOWS_1256,
+        1257 => WINDOWS_1257,
+        1258 => WINDOWS_1258,
+        10000 => MACINTOSH,
+        65001 => UTF_8,
+        _ => WINDOWS_1252,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// \uN, which is built here to keep it out of string literals
+    fn u(code: i32) -> String {
+        format!("\\u{}", code)
+    }
+
+    #[test]
+    fn decode_unicode_test() {
+        let decoder = Decoder::default();
+        let source = format!("{};{};{};", u(30740), u(31350), u(12290));
+        assert_eq!("研究。", decoder.decode(&source, None));
+        let source = format!("{};{}; ({};C)", u(20307), u(28201), u(176));
+        assert_eq!("体温 (°C)", decoder.decode(&source, None));
+        // characters above 32767 are negative
+        let source = format!(r"\uc0{} {}", u(-24901), u(25968));
+        assert_eq!("麻数", decoder.decode(&source, None));
+        // surrogate pair
+        let source = format!("{}?{}?", u(-10179), u(-8704));
+        assert_eq!(
+            char::from_u32(0x1F600).unwrap().to_string(),
+            decoder.decode(&source, None)
+        );
+        // fallback characters are skipped by \ucN, a control word is one character
+        let source = format!(r"\uc2{} ab c{}\'3f\'3fd{{\super a}}", u(25968), u(25968));
+        assert_eq!(r"数 c数d{\super a}", decoder.decode(&source, None));
+        // ideographic spaces of indents are kept in groups
+        let indent = format!(r"{{\uc0{} }}", u(12288));
+        assert_eq!(
+            format!("{{{}}}{}12 (5.0)", indent, indent),
+            decoder.decode(&format!("{}{}?12 (5.0)", indent, u(12288)), None)
+        );
+    }
+
+    #[test]
+    fn decode_hex_test() {
+        let header =
+            Header::parse(br"{\rtf1\ansi\ansicpg936{\fonttbl{\f1\fnil\fcharset238 Arial CE;}}}")
+                .unwrap();
+        let decoder = Decoder::new(&header);
+        assert_eq!(
+            "宋体 (N=3)",
+            decoder.decode(r"\'cb\'ce\'cc\'e5 (N=3)", None)
+        );
+        // code page of font goes before the one of document
+        assert_eq!("\u{15a}", decoder.decode(r"\'8c", Some(1)));
+        assert_eq!(
+            "\\f1 \u{15a}{\\f0 宋}",
+            decoder.decode(r"\f1 \'8c{\f0 \'cb\'ce}", None)
+        );
+        // decoded braces and backslashes are still text
+        assert_eq!(r"a\\b", decoder.decode(r"a\'5cb", None));
+        assert_eq!(r"a\", decoder.decode(r"a\", None));
+    }
+}
@@ -0,0 +1,102 @@
+/// escape braces and backslashes in plain text, such as the reply of llm, so that they will not
+/// be regarded as rtf groups or control words, for example: {a}\b => \{a\}\\b
+pub fn escape_text(source: &str) -> String {
+    let mut result = String::with_capacity(source.len());
+    for c in source.chars() {
+        if matches!(c, '{' | '}' | '\\') {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// write non-ascii characters as "\uN?", N is a signed 16-bit integer, so that characters above
+/// 32767 are written as negative numbers, and characters beyond BMP are written as surrogate pairs
+///
+/// the "?" is the fallback character, which requires "\uc1" in current group, for example:
+/// "≥ 3" => "\u8805? 3"
+pub fn encode_unicode(source: &str) -> String {
+    let mut result = String::with_capacity(source.len());
+    let mut buffer = [0u16; 2];
+    for c in source.chars() {
+        if c.is_ascii() {
+            result.push(c);
+            continue;
+        }
+        for unit in c.encode_utf16(&mut buffer) {
+            result.push_str(&format!("\\u{}?", *unit as i16));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// decode what encoder writes, escapes and "\uN?" only
+    fn decode(source: &str) -> String {
+        let mut units = vec![];
+        let mut result = String::new();
+        let mut chars = source.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                result.push_str(&String::from_utf16(&units).unwrap());
+                units.clear();
+                result.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('u') => {
+                    let mut number = String::new();
+                    while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || *c == '-') {
+                        number.push(c);
+                    }
+                    assert_eq!(Some('?'), chars.next());
+                    units.push(number.parse::<i16>().unwrap() as u16);
+                }
+                Some(c) => {
+                    result.push_str(&String::from_utf16(&units).unwrap());
+                    units.clear();
+                    result.push(c);
+                }
+                None => unreachable!(),
+            }
+        }
+        result.push_str(&String::from_utf16(&units).unwrap());
+        result
+    }
+
+    #[test]
+    fn escape_text_test() {
+        assert_eq!(r"\{a\}\\b", escape_text(r"{a}\b"));
+        assert_eq!("Male", escape_text("Male"));
+    }
+
+    #[test]
+    fn encode_unicode_test() {
+        assert_eq!(r"\u8805? 3", encode_unicode("≥ 3"));
+        assert_eq!(r"\u177?", encode_unicode("±"));
+        assert_eq!(r"\u8220?a\u8221?", encode_unicode("\u{201C}a\u{201D}"));
+        // above 32767
+        assert_eq!(r"\u-1?", encode_unicode("\u{FFFF}"));
+        // beyond BMP
+        assert_eq!(r"\u-10187?\u-9216?", encode_unicode("\u{1D400}"));
+    }
+
+    #[test]
+    fn round_trip_test() {
+        for source in [
+            "Male",
+            "≥ 3 mg/kg ± 0.5 µg, 37 °C",
+            "\u{201C}Subject\u{201D} {a} \\b",
+            "研究药物\u{1D400}\u{FFFF}",
+            "",
+        ] {
+            let encoded = encode_unicode(&escape_text(source));
+            assert!(encoded.is_ascii());
+            assert_eq!(source, decode(&encoded));
+        }
+    }
+}
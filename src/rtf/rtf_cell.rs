@@ -1,13 +1,52 @@
-use std::{cell::RefCell, collections::HashMap};
+use std::{cell::RefCell, collections::HashMap, sync::LazyLock};
 
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 use super::symbol::{CELL, LINE};
 
 #[derive(Debug, Clone)]
 pub enum RtfCell {
     General(GeneralCell),
-    FootNote(String),
+    /// text in page headers, such as {\header ...}
+    Header(GeneralCell),
+    /// text in page footers, such as {\footer ...}
+    Footer(GeneralCell),
+    /// paragraphs before the first table
+    Title(GeneralCell),
+    /// fields of document information, such as {\title ...} in \info
+    Info(GeneralCell),
+    /// paragraphs after the final table row of each page
+    FootNote(GeneralCell),
+}
+
+impl RtfCell {
+    /// lines of the cell, whatever kind it is
+    pub fn general(&self) -> &GeneralCell {
+        match self {
+            RtfCell::General(cell)
+            | RtfCell::Header(cell)
+            | RtfCell::Footer(cell)
+            | RtfCell::Title(cell)
+            | RtfCell::Info(cell)
+            | RtfCell::FootNote(cell) => cell,
+        }
+    }
+
+    /// a cell of the same kind with lines changed
+    pub fn map_general<F>(&self, mapper: F) -> RtfCell
+    where
+        F: FnOnce(&GeneralCell) -> GeneralCell,
+    {
+        match self {
+            RtfCell::General(cell) => RtfCell::General(mapper(cell)),
+            RtfCell::Header(cell) => RtfCell::Header(mapper(cell)),
+            RtfCell::Footer(cell) => RtfCell::Footer(mapper(cell)),
+            RtfCell::Title(cell) => RtfCell::Title(mapper(cell)),
+            RtfCell::Info(cell) => RtfCell::Info(mapper(cell)),
+            RtfCell::FootNote(cell) => RtfCell::FootNote(mapper(cell)),
+        }
+    }
 }
 
 // cells which contents are enclosed in curly brackets
@@ -16,8 +55,39 @@ pub struct GeneralCell {
     pub lines: Vec<String>,
     pub translated_lines: Vec<String>,
     pub styles: String,
+    /// position of cell in table, which helps translating short cells
+    pub context: CellContext,
+}
+
+/// plain text of cells around a cell in table, such as "Total" for "合计" in a summary row
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CellContext {
+    pub title: Option<String>,
+    /// header of the column the cell is in
+    pub column: Option<String>,
+    /// first cell of the row the cell is in
+    pub row: Option<String>,
+    pub role: CellRole,
 }
 
+/// where a cell is in table, the same line may be translated differently in different roles
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CellRole {
+    /// not in table, such as titles and footnotes
+    #[default]
+    Text,
+    /// in header row of table
+    Header,
+    /// first cell of a row in table body
+    Label,
+    /// other cells in table body
+    Value,
+}
+
+/// control words and curly brackets removed from plain text of cells
+static CONTROL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\\[a-zA-Z]+-?\d* ?|[{}]").unwrap());
+
 impl GeneralCell {
     pub fn new(bytes: &[u8]) -> anyhow::Result<Self> {
         let lines = String::from_utf8(bytes.to_vec())?;
@@ -30,8 +100,19 @@ impl GeneralCell {
                 .collect::<Vec<String>>(),
             translated_lines: Vec::with_capacity(lines.len()),
             styles: style,
+            context: CellContext::default(),
         })
     }
+
+    /// lines joined by spaces, without control words and curly brackets
+    pub fn text(&self) -> String {
+        self.lines
+            .iter()
+            .map(|line| CONTROL.replace_all(line, "").trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
     pub fn process<F>(mut self, processor: F) -> Self
     where
         F: Fn(&str) -> String,
@@ -83,13 +164,12 @@ impl RtfCellSet {
     pub fn term_set(&self) -> Vec<(String, String)> {
         let mut set: HashMap<String, String> = HashMap::new();
         for (_, cell) in self.set.borrow().iter() {
-            if let RtfCell::General(cell) = cell {
-                let sources = cell.lines.clone();
-                let translations = cell.translated_lines.clone();
-                for (i, source) in sources.iter().enumerate() {
-                    if let Some(translation) = translations.get(i) {
-                        set.insert(source.to_owned(), translation.to_owned());
-                    }
+            let cell = cell.general();
+            let sources = cell.lines.clone();
+            let translations = cell.translated_lines.clone();
+            for (i, source) in sources.iter().enumerate() {
+                if let Some(translation) = translations.get(i) {
+                    set.insert(source.to_owned(), translation.to_owned());
                 }
             }
         }
@@ -99,7 +179,7 @@ impl RtfCellSet {
     pub fn rebuild(&self, mapper: &HashMap<String, String>) -> Self {
         let mut new_set = self.clone();
         for (id, cell) in self.set.borrow().iter() {
-            if let RtfCell::General(cell) = cell {
+            let new_cell = cell.map_general(|cell| {
                 let mut new_cell = cell.clone();
                 for (index, source) in cell.lines.iter().enumerate() {
                     let origin_translate = cell.lines.get(index).unwrap();
@@ -109,8 +189,9 @@ impl RtfCellSet {
                         }
                     }
                 }
-                new_set.update(*id, &RtfCell::General(new_cell));
-            }
+                new_cell
+            });
+            new_set.update(*id, &new_cell);
         }
         new_set
     }
@@ -138,7 +219,7 @@ fn split_cell_content_style(source: &str) -> (String, String) {
 #[cfg(test)]
 mod tests {
 
-    use crate::rtf::processor::{double_quote, single_quote};
+    use crate::rtf::processor::{double_quote, single_quote, Decoder};
 
     use super::*;
     #[test]
@@ -171,4 +252,13 @@ Adverse events were coded using MedDRA Version 25.0 and severity were graded acc
         assert_eq!(c.lines.len(), 2);
         c.lines.iter().for_each(|line| println!("{}", line));
     }
+
+    #[test]
+    fn cell_text_test() {
+        let cell =
+            GeneralCell::new(r"{\line}\u20363;\u25968;{\super a}{\line}(N=198)\cell".as_bytes())
+                .unwrap()
+                .process(|line| Decoder::default().decode(line, None));
+        assert_eq!("例数a (N=198)", cell.text());
+    }
 }
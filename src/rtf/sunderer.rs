@@ -3,44 +3,96 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use crate::translator::Translator;
+use crate::translator::{DryRunReport, TranslateReport, Translator};
 
 use super::{
-    control_word::ControlWordIterator,
-    font_definition,
-    footnote::footnote_position,
-    processor::{decode_unicode, depress_lf_cr, double_quote, percent, single_quote},
-    rtf_cell::{GeneralCell, RtfCell, RtfCellSet},
-    symbol::WINDOW_CTRL,
+    error::{SplitError, SplitReason},
+    footnote::footnote_regions,
+    header::Header,
+    lexer::{Token, TokenKind},
+    processor::{depress_lf_cr, double_quote, percent, single_quote, Decoder},
+    rtf_cell::{CellContext, CellRole, GeneralCell, RtfCell, RtfCellSet},
     template::TemplateGenerator,
-    utils::{group_position, pattern_position},
+    tree::{self, Group, Node},
 };
 
+/// parts of document found by walking through the group tree, positions are byte ranges in source
+#[derive(Debug, PartialEq)]
+enum Piece {
+    /// a group following a font control word, such as {Male\cell} in \f1{Male\cell}, the code
+    /// of font and the area it is in
+    Cell(usize, usize, i32, Area),
+    /// text which is not in a cell, such as 第1页 in {\header\pard\qr 第1页\par}, the font it
+    /// is shown in and the area it is in
+    Text(usize, usize, Option<i32>, Area),
+    /// \row, which ends a table row
+    RowEnd,
+}
+
+/// areas of document, text in which is translated as different kinds of cells
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Area {
+    /// tables and the text after them
+    Body,
+    /// paragraphs before the first table
+    Title,
+    Header,
+    Footer,
+    Info,
+    /// paragraphs after the final table row of each page
+    FootNote,
+}
+
+impl Area {
+    fn cell(&self, cell: GeneralCell) -> RtfCell {
+        match self {
+            Area::Body => RtfCell::General(cell),
+            Area::Title => RtfCell::Title(cell),
+            Area::Header => RtfCell::Header(cell),
+            Area::Footer => RtfCell::Footer(cell),
+            Area::Info => RtfCell::Info(cell),
+            Area::FootNote => RtfCell::FootNote(cell),
+        }
+    }
+}
+
 pub struct SundererParam<'a> {
     pub source: &'a Path,
     pub workspace: &'a Path,
+    /// collect cells only, template is not written into workspace
+    pub dry_run: bool,
 }
 
 /// to seperate rtf to template and contents
 pub struct Sunderer {
-    generator: TemplateGenerator,
+    /// none in a dry run
+    generator: Option<TemplateGenerator>,
     bytes: Vec<u8>,
     cell_set: RtfCellSet,
     template_path: PathBuf,
 }
 
 impl Sunderer {
-    pub fn new(param: &SundererParam) -> anyhow::Result<Self> {
-        let SundererParam { source, workspace } = param;
-        let file_stem = source.file_stem().unwrap().to_string_lossy();
+    pub fn new(param: &SundererParam) -> Result<Self, SplitError> {
+        let SundererParam {
+            source,
+            workspace,
+            dry_run,
+        } = param;
+        let file_stem = source.file_stem().unwrap_or_default().to_string_lossy();
         let template = workspace.join(format!("{}.rtf.tmp", file_stem));
         let template = template.as_path();
-        if template.exists() {
-            fs::remove_file(template)?;
-        }
-        let generator = TemplateGenerator::new(&template)?;
+        let generator = match dry_run {
+            true => None,
+            false => {
+                if template.exists() {
+                    fs::remove_file(template).map_err(|e| SplitError::io(0, e))?;
+                }
+                Some(TemplateGenerator::new(template).map_err(|e| SplitError::io(0, e))?)
+            }
+        };
         let cell_set = RtfCellSet::new();
-        let bytes = fs::read(source)?;
+        let bytes = fs::read(source).map_err(|e| SplitError::io(0, e))?;
         Ok(Sunderer {
             generator,
             bytes,
@@ -49,119 +101,570 @@ impl Sunderer {
         })
     }
     /// split content rtf to cell set and template, return cell set
-    pub fn split(&mut self) -> anyhow::Result<&mut Self> {
-        let font_set = font_definition::font_set(&self.bytes);
-        let mut content_start = 0;
-        // find out the position of content part
-        let window_ctrl = pattern_position(&WINDOW_CTRL, &self.bytes, 0).unwrap();
-        let control_word_iterator = ControlWordIterator::new(&self.bytes, window_ctrl.0);
-
-        let mut last_group: Option<(usize, usize)> = None;
-        while !control_word_iterator.is_drained() {
-            let cw = control_word_iterator.next();
-            match cw {
-                Some(cw) => {
-                    let b = self.bytes[cw.0..cw.1].to_vec();
-                    let control_word = String::from_utf8(b).unwrap();
-                    if let Some(_) = font_set.get(&control_word[1..]) {
-                        let start = cw.1;
-                        let group: (usize, usize) =
-                            group_position(&self.bytes, start, self.bytes.len()).unwrap();
-
-                        // check contents between 2 groups whether it is a footnote
-                        if let Some(last_group) = last_group {
-                            if let Some(footnote_range) =
-                                footnote_position(&self.bytes, (last_group.1, group.0))
-                            {
-                                if let Some(footnote) =
-                                    self.bytes.get(footnote_range.0..footnote_range.1)
-                                {
-                                    let footnote_cell =
-                                        RtfCell::FootNote(String::from_utf8(footnote.to_vec())?);
-                                    let id = self.cell_set.add(footnote_cell);
-                                    self.generator
-                                        .push(&self.bytes[content_start..footnote_range.0])?;
-                                    self.generator.add_placeholder(id)?;
-                                    content_start = footnote_range.1;
-                                }
-                            }
-                        }
+    pub fn split(&mut self) -> Result<&mut Self, SplitError> {
+        let root = tree::parse(&self.bytes)?;
+        let header = Header::from_tree(&root, &self.bytes);
+        let decoder = Decoder::new(&header);
+        let mut collector = Collector::new(&self.bytes, &header, &root);
+        collector.visit(&root, Area::Body);
+        let pieces = collector.pieces;
 
-                        let cell = GeneralCell::new(&self.bytes[group.0 + 1..group.1 - 1])?;
-                        let cell = cell
-                            .process(decode_unicode)
-                            .process(depress_lf_cr)
-                            .process(percent)
-                            .process(single_quote)
-                            .process(double_quote);
-
-                        let id = self.cell_set.add(RtfCell::General(cell));
-
-                        // push contents into buffer
-                        self.generator.push(&self.bytes[content_start..group.0])?;
-                        self.generator.add_placeholder(id)?;
-                        content_start = group.1;
-                        control_word_iterator.set_cursor(group.1 + 1);
-                        last_group = Some(group);
+        let mut content_start = 0;
+        // ids of general cells in each table row
+        let mut rows: Vec<Vec<usize>> = vec![];
+        let mut row: Vec<usize> = vec![];
+        for piece in pieces {
+            let (id, start, end) = match piece {
+                Piece::RowEnd => {
+                    if !row.is_empty() {
+                        rows.push(std::mem::take(&mut row));
                     }
+                    continue;
                 }
-                None => {
-                    control_word_iterator.cursor_move_one_step();
+                Piece::Cell(start, end, font, area) => {
+                    // curly brackets of group are written back by stuffer
+                    let content = &self.bytes[start + 1..end - 1];
+                    let cell = general_cell(content, start + 1, Some(font), &decoder)?;
+                    let id = self.cell_set.add(area.cell(cell));
+                    if area == Area::Body {
+                        row.push(id);
+                    }
+                    (id, start, end)
                 }
+                Piece::Text(start, end, font, area) => {
+                    let cell = general_cell(&self.bytes[start..end], start, font, &decoder)?;
+                    (self.cell_set.add(area.cell(cell)), start, end)
+                }
+            };
+            // push contents into buffer
+            if let Some(generator) = self.generator.as_mut() {
+                generator
+                    .push(&self.bytes[content_start..start])
+                    .and_then(|_| generator.add_placeholder(id))
+                    .map_err(|e| SplitError::io(start, e))?;
             }
+            content_start = end;
+        }
+        if !row.is_empty() {
+            rows.push(row);
+        }
+        self.assign_context(&rows);
+        if let Some(generator) = self.generator.as_mut() {
+            generator
+                .push(&self.bytes[content_start..])
+                .and_then(|_| generator.flush())
+                .map_err(|e| SplitError::io(content_start, e))?;
         }
+        Ok(self)
+    }
 
-        // check contents between 2 groups whether it is a footnote
-        if let Some(last_group) = last_group {
-            if let Some(footnote_range) =
-                footnote_position(&self.bytes, (last_group.1, self.bytes.len()))
-            {
-                if let Some(footnote) = self.bytes.get(footnote_range.0..footnote_range.1) {
-                    let footnote_cell = RtfCell::FootNote(String::from_utf8(footnote.to_vec())?);
-                    // let footnote_cell = translator.translate(&footnote_cell);
-                    let id = self.cell_set.add(footnote_cell);
-                    self.generator
-                        .push(&self.bytes[content_start..footnote_range.0])?;
-                    self.generator.add_placeholder(id)?;
-                    content_start = footnote_range.1;
+    /// find out title, column header and row label of cells by their positions in table
+    ///
+    /// rows with one cell before the first row with several cells are titles, which is followed
+    /// by column headers, the first cell of other rows is the row label. a row with one cell after
+    /// body rows starts a new table, such as the table on next page
+    fn assign_context(&mut self, rows: &[Vec<usize>]) {
+        let mut titles: Vec<String> = vec![];
+        let mut header: Option<Vec<String>> = None;
+        let mut in_body = false;
+        for row in rows {
+            let cells = row
+                .iter()
+                .filter_map(|id| match self.cell_set.find(*id) {
+                    Some(RtfCell::General(cell)) => Some((*id, cell)),
+                    _ => None,
+                })
+                .collect::<Vec<(usize, GeneralCell)>>();
+            let texts = cells
+                .iter()
+                .map(|(_, cell)| cell.text())
+                .collect::<Vec<String>>();
+            if cells.len() == 1 && (header.is_none() || in_body) {
+                if in_body {
+                    titles.clear();
+                    header = None;
+                    in_body = false;
                 }
+                titles.push(texts[0].clone());
+                continue;
+            }
+            let title = Some(titles.join(" ")).filter(|title| !title.is_empty());
+            let header_row = match &header {
+                None => {
+                    header = Some(texts.clone());
+                    true
+                }
+                Some(_) => {
+                    in_body = true;
+                    false
+                }
+            };
+            for (column, (id, mut cell)) in cells.into_iter().enumerate() {
+                cell.context = if header_row {
+                    CellContext {
+                        title: title.clone(),
+                        role: CellRole::Header,
+                        ..Default::default()
+                    }
+                } else {
+                    CellContext {
+                        title: title.clone(),
+                        column: header
+                            .as_ref()
+                            .filter(|header| header.len() == texts.len())
+                            .and_then(|header| header.get(column))
+                            .filter(|text| !text.is_empty())
+                            .cloned(),
+                        row: Some(texts[0].clone()).filter(|text| column > 0 && !text.is_empty()),
+                        role: if column == 0 {
+                            CellRole::Label
+                        } else {
+                            CellRole::Value
+                        },
+                    }
+                };
+                self.cell_set.update(id, &RtfCell::General(cell));
             }
         }
-        self.generator.push(&self.bytes[content_start..])?;
-        self.generator.flush()?;
-        Ok(self)
     }
 
-    pub fn translate(&mut self, translator: &mut Translator) -> anyhow::Result<RtfCellSet> {
-        for id in 0..self.cell_set.size() {
-            if let Some(cell) = self.cell_set.find(id) {
-                let cell = translator.translate(&cell);
-                self.cell_set.update(id, &cell)
-            }
+    pub fn translate(
+        &mut self,
+        translator: &Translator,
+        report: &mut TranslateReport,
+    ) -> anyhow::Result<RtfCellSet> {
+        let cells = (0..self.cell_set.size())
+            .filter_map(|id| self.cell_set.find(id))
+            .collect::<Vec<RtfCell>>();
+        for (id, cell) in translator.translate_all(&cells, report).iter().enumerate() {
+            self.cell_set.update(id, cell);
         }
         Ok(self.cell_set.clone())
     }
 
+    /// count lines to be translated without translating them
+    pub fn estimate(&self, translator: &Translator, report: &mut DryRunReport) {
+        let cells = (0..self.cell_set.size())
+            .filter_map(|id| self.cell_set.find(id))
+            .collect::<Vec<RtfCell>>();
+        translator.estimate(&cells, report);
+    }
+
     pub fn template_path(&self) -> PathBuf {
         self.template_path.clone()
     }
 }
 
+/// control words which start or end paragraphs, table rows or pages
+const BREAKS: [&str; 8] = [
+    "par", "pard", "sect", "page", "cell", "row", "trowd", "intbl",
+];
+/// fields of \info which are shown as text, such as title and author in file properties
+const INFO_FIELDS: [&str; 7] = [
+    "title", "subject", "author", "operator", "keywords", "doccomm", "company",
+];
+
+/// walk through groups in document order and collect cells, text and ends of rows
+///
+/// a group following a font control word is a cell, such as \f1\fs21{Male\cell}. in page headers,
+/// footers, \info fields, paragraphs before the first table and footnotes after the final row of
+/// each page, text which is not in a cell is collected as well. destinations such as font table
+/// are skipped
+struct Collector<'a> {
+    bytes: &'a [u8],
+    header: &'a Header,
+    pieces: Vec<Piece>,
+    /// whether the first table of current page was found, paragraphs before it are titles
+    in_table: bool,
+    /// byte ranges of footnotes
+    footnotes: Vec<(usize, usize)>,
+}
+
+impl<'a> Collector<'a> {
+    fn new(bytes: &'a [u8], header: &'a Header, root: &Group) -> Self {
+        Collector {
+            bytes,
+            header,
+            pieces: vec![],
+            in_table: false,
+            footnotes: footnote_regions(root),
+        }
+    }
+
+    fn visit(&mut self, group: &Group, area: Area) {
+        // the latest font selected in a run of control words, such as \f1\f0\f2\fs21
+        let mut selected: Option<i32> = None;
+        // the latest font in group, which text following it is shown in
+        let mut current: Option<i32> = None;
+        // start of text which is not in a cell, and the end of the last visible token in it
+        let mut text: Option<(usize, Option<usize>)> = None;
+        for node in group.children.iter() {
+            let token = match node {
+                Node::Group(child) => {
+                    // groups such as fields and superscripts in text go with the text
+                    if let (None, Some((start, _))) = (selected, text) {
+                        if is_inline(child) {
+                            text = Some((start, Some(child.end)));
+                            continue;
+                        }
+                    }
+                    self.end_text(&mut text, current, area);
+                    if let Some(font) = selected {
+                        if area == Area::Body && (child.contains("cell") || child.contains("intbl"))
+                        {
+                            self.in_table = true;
+                        }
+                        let area = self.area(area, child.start);
+                        self.pieces
+                            .push(Piece::Cell(child.start, child.end, font, area));
+                    } else if child.is_header() {
+                        self.visit(child, Area::Header);
+                    } else if child.is_footer() {
+                        self.visit(child, Area::Footer);
+                    } else if child.name() == Some("info") {
+                        child
+                            .groups()
+                            .filter(|field| {
+                                field.name().is_some_and(|name| INFO_FIELDS.contains(&name))
+                            })
+                            .for_each(|field| self.visit(field, Area::Info));
+                    } else if !child.is_hidden() {
+                        self.visit(child, area);
+                    }
+                    selected = None;
+                    continue;
+                }
+                Node::Token(token) => token,
+            };
+            if area == Area::Body && ["trowd", "intbl", "cell"].iter().any(|w| token.is_word(w)) {
+                self.in_table = true;
+            }
+            // titles of the next table follow a page or section break
+            if area == Area::Body && (token.is_word("page") || token.is_word("sect")) {
+                self.in_table = false;
+            }
+            if token.is_word("row") {
+                self.end_text(&mut text, current, area);
+                self.pieces.push(Piece::RowEnd);
+            } else if let Some(code) = font(token, self.header) {
+                self.end_text(&mut text, current, area);
+                selected = Some(code);
+                current = Some(code);
+            } else if self.area(area, token.start) != Area::Body && is_text(token) {
+                if token.is_visible(self.bytes) {
+                    let (start, end) = trim(token, self.bytes);
+                    let start = text.map_or(start, |(start, _)| start);
+                    text = Some((start, Some(end)));
+                    selected = None;
+                } else if text.is_none() && token.is_word("uc") {
+                    // \ucN goes with the \uN following it
+                    text = Some((token.start, None));
+                }
+            } else {
+                self.end_text(&mut text, current, area);
+                if token.is_visible(self.bytes) {
+                    selected = None;
+                }
+            }
+        }
+        self.end_text(&mut text, current, area);
+    }
+
+    /// text which is not in a cell, it ends before tokens of other kinds
+    fn end_text(
+        &mut self,
+        text: &mut Option<(usize, Option<usize>)>,
+        font: Option<i32>,
+        area: Area,
+    ) {
+        if let Some((start, Some(end))) = text.take() {
+            let area = self.area(area, start);
+            self.pieces.push(Piece::Text(start, end, font, area));
+        }
+    }
+
+    /// paragraphs of body after the final row of a page are footnotes, and the ones before the
+    /// first table are titles
+    fn area(&self, area: Area, position: usize) -> Area {
+        match area {
+            Area::Body
+                if self
+                    .footnotes
+                    .iter()
+                    .any(|(start, end)| (*start..*end).contains(&position)) =>
+            {
+                Area::FootNote
+            }
+            Area::Body if !self.in_table => Area::Title,
+            _ => area,
+        }
+    }
+}
+
+/// whether token is part of text, such as 表, \'b1\'ed and \{
+fn is_text(token: &Token) -> bool {
+    match &token.kind {
+        TokenKind::Text | TokenKind::Hex(_) | TokenKind::ControlSymbol(_) => true,
+        TokenKind::ControlWord { name, .. } => name == "u" || name == "uc",
+        _ => false,
+    }
+}
+
+/// whether group is a part of paragraph, such as {\super a} and {\field ...}, rather than
+/// paragraphs, table rows or destinations
+fn is_inline(group: &Group) -> bool {
+    !group.is_header()
+        && !group.is_footer()
+        && group.name() != Some("info")
+        && !BREAKS.iter().any(|name| group.contains(name))
+}
+
+/// range of token without spaces and line breaks around text, which are kept in template
+fn trim(token: &Token, bytes: &[u8]) -> (usize, usize) {
+    let content = &bytes[token.start..token.end];
+    match token.kind {
+        TokenKind::Text => {
+            let leading = content.len() - content.trim_ascii_start().len();
+            let trailing = content.len() - content.trim_ascii_end().len();
+            (token.start + leading, token.end - trailing)
+        }
+        _ => (token.start, token.end),
+    }
+}
+
+/// split content into lines and decode them, offset is where content starts in source
+fn general_cell(
+    content: &[u8],
+    offset: usize,
+    font: Option<i32>,
+    decoder: &Decoder,
+) -> Result<GeneralCell, SplitError> {
+    let cell = GeneralCell::new(content).map_err(|_| invalid_utf8(content, offset))?;
+    // quotes such as {\uc0\u39 } are replaced before decoding
+    Ok(cell
+        .process(depress_lf_cr)
+        .process(percent)
+        .process(single_quote)
+        .process(double_quote)
+        .process(|line| decoder.decode(line, font)))
+}
+
+/// error of content which is not utf-8, offset is where the first invalid byte is
+fn invalid_utf8(content: &[u8], offset: usize) -> SplitError {
+    let valid = std::str::from_utf8(content).map_or_else(|e| e.valid_up_to(), |_| 0);
+    SplitError::new(offset + valid, SplitReason::InvalidUtf8)
+}
+
+/// font selected by token if it is a control word such as \f1, and the font is in font table
+fn font(token: &Token, header: &Header) -> Option<i32> {
+    match &token.kind {
+        TokenKind::ControlWord {
+            name,
+            param: Some(param),
+        } if name == "f" && header.font(*param).is_some() => Some(*param),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::translator::Translator;
+    use std::env;
+
+    use crate::{
+        translator::{
+            Glossary, LlmClient, LlmConfig, TranslateReport, TranslationMemory, Translator,
+            TranslatorParam,
+        },
+        utils::Language,
+    };
 
     use super::*;
     #[test]
     fn sunderder_test() -> anyhow::Result<()> {
-        let mut translator = Translator::new("<your api key>");
+        let translator = Translator::new(TranslatorParam {
+            backend: Box::new(LlmClient::new("<your api key>")),
+            config: LlmConfig::default(),
+            memory: TranslationMemory::default(),
+            glossary: Glossary::default(),
+            budget: None,
+            source_language: Language::Chinese,
+            target_language: Language::English,
+        });
         let source = Path::new(
             r"D:\projects\rusty\playground\rtf\.sample_data\test\t-14-01-03-08-pr-fas.rtf",
         );
         let workspace = Path::new(r"D:\projects\rusty\playground\rtf\.sample_data\test\workspace");
-        let mut sunderder = Sunderer::new(&SundererParam { source, workspace })?;
-        let cell_set = sunderder.split()?.translate(&mut translator)?;
+        let mut sunderder = Sunderer::new(&SundererParam {
+            source,
+            workspace,
+            dry_run: false,
+        })?;
+        let mut report = TranslateReport::default();
+        let cell_set = sunderder.split()?.translate(&translator, &mut report)?;
         println!("{:?}", cell_set);
         Ok(())
     }
+
+    #[test]
+    fn context_test() -> anyhow::Result<()> {
+        let workspace = env::temp_dir().join("transient_context_test");
+        fs::create_dir_all(&workspace)?;
+        let source = workspace.join("t-14-01.rtf");
+        // non-ascii characters are written as \uN; in rtf
+        let cell = |text: &str| {
+            let text = text
+                .chars()
+                .map(|c| match c.is_ascii() {
+                    true => c.to_string(),
+                    false => format!("\\u{};", c as u32),
+                })
+                .collect::<String>();
+            format!(r"\pard\plain\intbl\ql\f1{{{}\cell}}", text)
+        };
+        let content = format!(
+            "{{\\rtf1\\ansi{{\\fonttbl{{\\f1\\froman\\fprq2\\fcharset0 SimSun;}}}}\n\\widowctrl\n{}\\row\n{}{}{}\\row\n{}{}{}\\row\n{}{}{}\\row\n}}",
+            cell("表 14.1 人口学资料"),
+            cell("项目"),
+            cell("试验组"),
+            cell("合计"),
+            cell("年龄"),
+            cell("例"),
+            cell("12"),
+            cell("合计"),
+            cell("20"),
+            cell("40"),
+        );
+        fs::write(&source, content)?;
+        let mut sunderer = Sunderer::new(&SundererParam {
+            source: &source,
+            workspace: &workspace,
+            dry_run: false,
+        })?;
+        sunderer.split()?;
+        let context = |id: usize| match sunderer.cell_set.find(id) {
+            Some(RtfCell::General(cell)) => cell.context,
+            _ => unreachable!(),
+        };
+        let title = Some("表 14.1 人口学资料".to_string());
+        assert_eq!(CellContext::default(), context(0));
+        assert_eq!(
+            CellContext {
+                title: title.clone(),
+                role: CellRole::Header,
+                ..Default::default()
+            },
+            context(2)
+        );
+        assert_eq!(
+            CellContext {
+                title: title.clone(),
+                column: Some("试验组".into()),
+                row: Some("年龄".into()),
+                role: CellRole::Value,
+            },
+            context(5)
+        );
+        assert_eq!(
+            CellContext {
+                title,
+                column: Some("项目".into()),
+                row: None,
+                role: CellRole::Label,
+            },
+            context(7)
+        );
+        fs::remove_dir_all(&workspace)?;
+        Ok(())
+    }
+
+    /// areas and contents of pieces collected from source, ends of rows are \row
+    fn collect(source: &[u8]) -> Vec<(Area, String)> {
+        let root = tree::parse(source).unwrap();
+        let header = Header::from_tree(&root, source);
+        let mut collector = Collector::new(source, &header, &root);
+        collector.visit(&root, Area::Body);
+        collector
+            .pieces
+            .iter()
+            .map(|piece| {
+                let (start, end, area) = match piece {
+                    Piece::Cell(start, end, _, area) | Piece::Text(start, end, _, area) => {
+                        (*start, *end, *area)
+                    }
+                    Piece::RowEnd => return (Area::Body, r"\row".into()),
+                };
+                (
+                    area,
+                    String::from_utf8_lossy(&source[start..end]).to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn collect_test() {
+        let source = br"{\rtf1{\fonttbl{\f1 SimSun;}}{\*\bkmkstart a}\f1\fs21{Male\cell}\f1{\f1{Female}\cell}\row\f1\fs16 \par Note\cf0{\par}}";
+        let ranges = collect(source)
+            .into_iter()
+            .map(|(_, range)| range)
+            .collect::<Vec<String>>();
+        assert_eq!(
+            vec![r"{Male\cell}", r"{\f1{Female}\cell}", r"\row", "Note"],
+            ranges
+        );
+    }
+
+    #[test]
+    fn collect_area_test() {
+        let source =
+            br"{\rtf1{\fonttbl{\f1 SimSun;}}{\info{\title Summary}{\author Zhang}{\creatim\yr2024}}
+{\header\pard\qr\f1 Page {\field{\*\fldinst PAGE}{\fldrslt 1}} of 2\par}
+{\footer\pard\plain\intbl\f1{Program\cell}\row}
+\pard\qc\f1 Table 14.1\par
+\trowd\cellx1000\pard\intbl\f1{Male\cell}\row
+\f1\fs16 Note}";
+        assert_eq!(
+            vec![
+                (Area::Info, "Summary".to_string()),
+                (Area::Info, "Zhang".into()),
+                (
+                    Area::Header,
+                    r"Page {\field{\*\fldinst PAGE}{\fldrslt 1}} of 2".into()
+                ),
+                (Area::Footer, r"{Program\cell}".into()),
+                (Area::Body, r"\row".into()),
+                (Area::Title, "Table 14.1".into()),
+                (Area::Body, r"{Male\cell}".into()),
+                (Area::Body, r"\row".into()),
+                (Area::FootNote, "Note".into()),
+            ],
+            collect(source)
+        );
+        // titles of each page
+        let source = br"{\rtf1{\fonttbl{\f1 SimSun;}}
+\pard\qc\f1 Table 14.1\par
+\trowd\cellx1000\pard\intbl\f1{Male\cell}\row
+\pard\f1 Note\par\sect
+\pard\qc\f1 Table 14.2\par
+\trowd\cellx1000\pard\intbl\f1{Female\cell}\row
+\pard\f1 Note\par\page
+\pard\qc\f1 Table 14.3\par
+\trowd\cellx1000\pard\intbl\f1{Total\cell}\row}";
+        assert_eq!(
+            vec![
+                (Area::Title, "Table 14.1".to_string()),
+                (Area::Body, r"{Male\cell}".into()),
+                (Area::Body, r"\row".into()),
+                (Area::FootNote, "Note".into()),
+                (Area::Title, "Table 14.2".into()),
+                (Area::Body, r"{Female\cell}".into()),
+                (Area::Body, r"\row".into()),
+                (Area::FootNote, "Note".into()),
+                (Area::Title, "Table 14.3".into()),
+                (Area::Body, r"{Total\cell}".into()),
+                (Area::Body, r"\row".into()),
+            ],
+            collect(source)
+        );
+    }
 }
@@ -1,14 +1,8 @@
 pub const LEFT_BRACE: u8 = b'{';
 pub const RIGHT_BRACE: u8 = b'}';
-pub const FONT_TBL: &'static [u8] = br"\fonttbl";
 pub const SLASH: u8 = b'\\';
-pub const WINDOW_CTRL: &'static [u8] = br"\widowctrl";
-pub const ALTHABET_RANGE_START: u8 = b'a';
-pub const ALTHABET_RANGE_END: u8 = b'z';
-pub const NUMBER_RANGE_START: u8 = b'0';
-pub const NUMBER_RANGE_END: u8 = b'9';
 pub const LF: u8 = b'\n';
 pub const CR: u8 = b'\r';
 pub const LINE: &'static str = r"{\line}";
 pub const CELL: &'static str = r"\cell";
-pub const CHCBPAT0: &'static str = r"\uc1\cf0\chcbpat0";
+pub const UC1: &str = r"\uc1 ";
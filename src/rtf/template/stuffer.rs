@@ -1,24 +1,35 @@
 use std::{
     fs::{File, OpenOptions},
-    io::{self, BufRead, BufReader, BufWriter, Write},
+    io::{self, BufReader, BufWriter, Read, Write},
     path::Path,
 };
 
-use regex::Regex;
+use regex::{NoExpand, Regex};
 
-use crate::rtf::{
-    rtf_cell::{RtfCell, RtfCellSet},
-    symbol::LINE,
+use crate::{
+    rtf::{
+        font_definition::{FontMapping, FontSubstitution},
+        processor::encode_unicode,
+        rtf_cell::{RtfCell, RtfCellSet},
+        symbol::{LINE, UC1},
+    },
+    utils::Language,
 };
 
 pub struct Stuffer {
     reader: BufReader<File>,
     writer: BufWriter<File>,
+    fonts: FontSubstitution,
 }
 
 pub struct StufferParam<'a> {
     pub template: &'a Path,
     pub destination: &'a Path,
+    /// fonts of source language in font table will be replaced by the one of target language
+    pub source_language: Language,
+    pub target_language: Language,
+    /// fonts configured by user, which go before the font of target language
+    pub font_mapping: &'a FontMapping,
 }
 
 impl Stuffer {
@@ -30,38 +41,58 @@ impl Stuffer {
             .open(param.destination)?;
         let reader = BufReader::new(reader);
         let writer = BufWriter::new(writer);
-        Ok(Stuffer { reader, writer })
+        let fonts = FontSubstitution::new(
+            param.source_language,
+            param.target_language,
+            param.font_mapping,
+        );
+        Ok(Stuffer {
+            reader,
+            writer,
+            fonts,
+        })
     }
     pub fn stuff(&mut self, data: &RtfCellSet) -> anyhow::Result<()> {
-        let mut line = String::new();
         let re = Regex::new(r"\{#(\d+)#\}")?;
+        let mut content = String::new();
+        self.reader.read_to_string(&mut content)?;
+        // font table is substituted before filling, fonts in contents of cells are kept
+        let content = self.fonts.apply(&content)?;
 
-        while let Ok(size) = self.reader.read_line(&mut line) {
-            if size.eq(&0) {
-                break;
-            }
-
-            let captures = re.captures_iter(&line);
+        for line in content.split_inclusive('\n') {
+            let captures = re.captures_iter(line);
             if captures.count().gt(&0) {
-                let mut alter_line = line.clone();
-                for caps in re.captures_iter(&line) {
+                let mut alter_line = line.to_string();
+                for caps in re.captures_iter(line) {
                     if let Some(id) = caps.get(1).map(|item| item.as_str()) {
                         if let Ok(id) = id.parse::<usize>() {
                             if let Some(cell) = data.find(id) {
                                 match cell {
-                                    RtfCell::General(cell) => {
+                                    // fields of \info are plain text, which can not be grouped
+                                    RtfCell::Info(cell) => {
+                                        let lines = cell.translated_lines.join(" ");
+                                        let uc = if lines.is_ascii() { "" } else { UC1 };
+                                        let content = format!("{}{}", uc, encode_unicode(&lines));
+                                        alter_line =
+                                            re.replace(&alter_line, NoExpand(&content)).to_string();
+                                    }
+                                    RtfCell::General(cell)
+                                    | RtfCell::Header(cell)
+                                    | RtfCell::Footer(cell)
+                                    | RtfCell::Title(cell)
+                                    | RtfCell::FootNote(cell) => {
+                                        let lines = cell.translated_lines.join(LINE);
+                                        // "\uN?" requires one fallback character to be skipped
+                                        let uc = if lines.is_ascii() { "" } else { UC1 };
                                         let content = format!(
-                                            "{{{}{}}}",
-                                            cell.translated_lines.join(LINE),
+                                            "{{{}{}{}}}",
+                                            uc,
+                                            encode_unicode(&lines),
                                             cell.styles
                                         );
                                         // let line = re.replace(&line, content).to_string();
-                                        alter_line = re.replace(&alter_line, content).to_string();
-                                        // io::copy(&mut line.as_bytes(), &mut self.writer)?;
-                                    }
-                                    RtfCell::FootNote(cell) => {
-                                        let content = format!("{}", cell);
-                                        alter_line = re.replace(&alter_line, content).to_string();
+                                        alter_line =
+                                            re.replace(&alter_line, NoExpand(&content)).to_string();
                                         // io::copy(&mut line.as_bytes(), &mut self.writer)?;
                                     }
                                 }
@@ -73,8 +104,6 @@ impl Stuffer {
             } else {
                 io::copy(&mut line.as_bytes(), &mut self.writer)?;
             }
-
-            line.clear();
         }
         Ok(())
     }
@@ -87,7 +116,7 @@ impl Stuffer {
 #[cfg(test)]
 mod test {
     use crate::rtf::{
-        rtf_cell::{GeneralCell, RtfCell},
+        rtf_cell::{CellContext, GeneralCell, RtfCell},
         symbol::CELL,
     };
 
@@ -97,6 +126,9 @@ mod test {
         let param = StufferParam {
             template: Path::new(r"D:\projects\rusty\playground\rtf\.sample_data\template.rtf"),
             destination: Path::new(r"D:\projects\rusty\playground\rtf\.sample_data\stuffer.rtf"),
+            source_language: Language::Chinese,
+            target_language: Language::English,
+            font_mapping: &FontMapping::default(),
         };
         let mut stuffer = Stuffer::new(&param).unwrap();
         let cell_set = RtfCellSet::new();
@@ -106,6 +138,7 @@ mod test {
                 lines: vec![r"\u9794;".into()],
                 translated_lines: vec![r"\u9794;".into()],
                 styles: style,
+                context: CellContext::default(),
             }));
         }
         stuffer.stuff(&cell_set).unwrap();
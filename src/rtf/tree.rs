@@ -0,0 +1,167 @@
+use super::{
+    error::{SplitError, SplitReason},
+    lexer::{Lexer, Token, TokenKind},
+};
+
+/// destinations which are never shown as contents of document
+const HIDDEN_DESTINATIONS: [&str; 4] = ["fonttbl", "colortbl", "stylesheet", "info"];
+/// groups of page headers, for all pages, left, right and first pages
+const HEADERS: [&str; 4] = ["header", "headerl", "headerr", "headerf"];
+/// groups of page footers, for all pages, left, right and first pages
+const FOOTERS: [&str; 4] = ["footer", "footerl", "footerr", "footerf"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Group(Group),
+    Token(Token),
+}
+
+/// a group enclosed in curly brackets, source[start..end] includes the brackets
+#[derive(Debug, Clone, PartialEq)]
+pub struct Group {
+    pub start: usize,
+    pub end: usize,
+    pub children: Vec<Node>,
+}
+
+impl Group {
+    /// name of the first control word, such as fonttbl, the \* of a destination is skipped
+    pub fn name(&self) -> Option<&str> {
+        let mut tokens = self.children.iter().map(|node| match node {
+            Node::Token(token) => Some(&token.kind),
+            Node::Group(_) => None,
+        });
+        match tokens.next()? {
+            Some(TokenKind::ControlSymbol(b'*')) => match tokens.next()? {
+                Some(TokenKind::ControlWord { name, .. }) => Some(name),
+                _ => None,
+            },
+            Some(TokenKind::ControlWord { name, .. }) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// whether it is an optional destination starting with \*, which readers may skip
+    pub fn is_optional(&self) -> bool {
+        matches!(
+            self.children.first(),
+            Some(Node::Token(Token {
+                kind: TokenKind::ControlSymbol(b'*'),
+                ..
+            }))
+        )
+    }
+
+    /// whether contents of it are not shown in document, such as font table and bookmarks
+    pub fn is_hidden(&self) -> bool {
+        self.is_optional()
+            || self
+                .name()
+                .is_some_and(|name| HIDDEN_DESTINATIONS.contains(&name))
+    }
+
+    /// whether it is a page header, such as {\header ...}
+    pub fn is_header(&self) -> bool {
+        self.name().is_some_and(|name| HEADERS.contains(&name))
+    }
+
+    /// whether it is a page footer, such as {\footer ...}
+    pub fn is_footer(&self) -> bool {
+        self.name().is_some_and(|name| FOOTERS.contains(&name))
+    }
+
+    /// the first group with the name, including itself and groups nested in it
+    pub fn find(&self, name: &str) -> Option<&Group> {
+        if self.name() == Some(name) {
+            return Some(self);
+        }
+        self.children.iter().find_map(|node| match node {
+            Node::Group(group) => group.find(name),
+            Node::Token(_) => None,
+        })
+    }
+
+    /// whether a control word with the name is in it or groups nested in it
+    pub fn contains(&self, name: &str) -> bool {
+        self.children.iter().any(|node| match node {
+            Node::Group(group) => group.contains(name),
+            Node::Token(token) => token.is_word(name),
+        })
+    }
+
+    /// groups directly in it
+    pub fn groups(&self) -> impl Iterator<Item = &Group> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Group(group) => Some(group),
+            Node::Token(_) => None,
+        })
+    }
+}
+
+/// parse rtf into a tree, the root is a virtual group covering the whole source
+pub fn parse(bytes: &[u8]) -> Result<Group, SplitError> {
+    let mut stack = vec![Group {
+        start: 0,
+        end: bytes.len(),
+        children: vec![],
+    }];
+    for token in Lexer::new(bytes) {
+        let token = token?;
+        match token.kind {
+            TokenKind::GroupStart => stack.push(Group {
+                start: token.start,
+                end: token.end,
+                children: vec![],
+            }),
+            TokenKind::GroupEnd => {
+                if stack.len() < 2 {
+                    return Err(SplitError::new(token.start, SplitReason::UnexpectedClosing));
+                }
+                let mut group = stack.pop().unwrap();
+                group.end = token.end;
+                stack.last_mut().unwrap().children.push(Node::Group(group));
+            }
+            _ => stack.last_mut().unwrap().children.push(Node::Token(token)),
+        }
+    }
+    if stack.len() > 1 {
+        let group = stack.pop().unwrap();
+        return Err(SplitError::new(group.start, SplitReason::Unclosed));
+    }
+    Ok(stack.pop().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn parse_test() {
+        let source = br"{\rtf1{\fonttbl{\f1 SimSun;}}{\*\bkmkstart a}\f1{Male\cell}}";
+        let root = parse(source).unwrap();
+        let document = root.groups().next().unwrap();
+        assert_eq!(Some("rtf"), document.name());
+        assert_eq!((0, source.len()), (document.start, document.end));
+        let fonttbl = root.find("fonttbl").unwrap();
+        assert_eq!(1, fonttbl.groups().count());
+        let bookmark = root.find("bkmkstart").unwrap();
+        assert!(bookmark.is_optional());
+        assert!(bookmark.is_hidden() && fonttbl.is_hidden());
+        let cell = document.groups().last().unwrap();
+        assert_eq!(br"{Male\cell}", &source[cell.start..cell.end]);
+        assert_eq!(None, cell.name());
+        assert!(!cell.is_hidden());
+        assert!(document.contains("cell") && !fonttbl.contains("cell"));
+    }
+
+    #[test]
+    fn unbalanced_test() {
+        assert_eq!(
+            Err(SplitError::new(0, SplitReason::Unclosed)),
+            parse(br"{\rtf1{\f1 a}")
+        );
+        assert_eq!(
+            Err(SplitError::new(7, SplitReason::UnexpectedClosing)),
+            parse(br"{\rtf1}}")
+        );
+    }
+}
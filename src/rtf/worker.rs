@@ -2,21 +2,35 @@ use std::{
     collections::HashMap,
     fs,
     path::{Path, PathBuf},
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
     thread,
 };
 
 use chrono::Local;
 
-use crate::{translator::Translator, utils::contains_chinese};
+use crate::{
+    translator::{
+        Budget, DryRunReport, Glossary, LlmConfig, TranslateError, TranslateReport,
+        TranslationBackend, TranslationMemory, Translator, TranslatorParam, Usage,
+    },
+    utils::Language,
+};
 
 use super::{
+    error::{SkipReason, SplitError},
+    font_definition::FontMapping,
+    layout,
     logger::Logger,
     rtf_cell::RtfCellSet,
     sunderer::{Sunderer, SundererParam},
     template::{Stuffer, StufferParam},
 };
 
+const TRANSLATION_MEMORY: &str = "translation_memory.jsonl";
+
 #[derive(Debug)]
 pub struct Worker {
     outputs: Vec<PathBuf>,
@@ -27,24 +41,67 @@ pub struct Worker {
     progress: Arc<Mutex<f64>>,
     translation_set: Arc<Mutex<HashMap<String, RtfCellSet>>>,
     term_set: Arc<Mutex<HashMap<String, String>>>,
-    translator: Arc<Mutex<Translator>>,
+    translator: Arc<Translator>,
+    /// outputs translated at the same time
+    concurrency: usize,
+    /// tokens consumed by the latest run of extract_translate
+    usage: Arc<Mutex<Usage>>,
+    /// lines counted by the latest dry run of extract_translate
+    dry_run_report: Arc<Mutex<DryRunReport>>,
+    /// outputs of the latest run which could not be split or translated, they are left untranslated
+    skipped: Arc<Mutex<Vec<(PathBuf, SkipReason)>>>,
+    source_language: Language,
+    target_language: Language,
+    font_mapping: FontMapping,
+    reflow: bool,
 }
 
 pub struct WorkerParam<'a> {
     pub workspace: &'a Path,
     pub destination_dir: &'a Path,
     pub outputs: &'a [PathBuf],
-    pub llm_api_key: &'a str,
+    pub backend: Box<dyn TranslationBackend>,
+    pub llm_config: LlmConfig,
+    /// json lines file of translation memory, default to translation_memory.jsonl in workspace
+    pub translation_memory: Option<&'a Path>,
+    /// toml file of glossary rules, such as sponsor names and protocol prefix
+    pub glossary: Option<&'a Path>,
+    /// cap on tokens or cost of requests, a cost budget requires price of the model
+    pub budget: Option<Budget>,
+    /// language of outputs, lines without text in it are kept as they are
+    pub source_language: Language,
+    /// language of translated outputs, which drives the prompt and fonts
+    pub target_language: Language,
+    /// toml file of fonts in translated outputs, such as SimSun => Times New Roman
+    pub font_mapping: Option<&'a Path>,
+    /// widen columns of translated outputs by widths of text, within the page width
+    pub reflow: bool,
 }
 
 impl Worker {
-    pub fn new(param: &WorkerParam) -> anyhow::Result<Worker> {
+    pub fn new(param: WorkerParam) -> anyhow::Result<Worker> {
         let WorkerParam {
             workspace,
             destination_dir,
             outputs,
-            llm_api_key,
+            backend,
+            llm_config,
+            translation_memory,
+            glossary,
+            budget,
+            source_language,
+            target_language,
+            font_mapping,
+            reflow,
         } = param;
+        if let Some(Budget::Cost(_)) = budget {
+            if !llm_config.prices.contains_key(&llm_config.model) {
+                anyhow::bail!(
+                    "price of model {} is required by a cost budget",
+                    llm_config.model
+                );
+            }
+        }
         for dir in [workspace, destination_dir] {
             if !dir.exists() {
                 fs::create_dir_all(dir)?;
@@ -52,7 +109,40 @@ impl Worker {
         }
         let log_path = workspace.join(log_file_name());
         let logger = Arc::new(Mutex::new(Logger::new(&log_path)?));
-        let translator = Translator::new(&llm_api_key);
+        let memory_path = match translation_memory {
+            Some(filepath) => filepath.to_owned(),
+            None => workspace.join(TRANSLATION_MEMORY),
+        };
+        let memory = TranslationMemory::open(&memory_path)?;
+        if memory.malformed() > 0 {
+            logger
+                .lock()
+                .unwrap()
+                .warn(&format!(
+                    "Skipped {} malformed lines of translation memory {:?}",
+                    memory.malformed(),
+                    memory_path
+                ))
+                .ok();
+        }
+        let glossary = match glossary {
+            Some(filepath) => Glossary::from_file(filepath)?,
+            None => Glossary::default(),
+        };
+        let font_mapping = match font_mapping {
+            Some(filepath) => FontMapping::from_file(filepath)?,
+            None => FontMapping::default(),
+        };
+        let concurrency = llm_config.concurrency;
+        let translator = Translator::new(TranslatorParam {
+            backend,
+            config: llm_config,
+            memory,
+            glossary,
+            budget,
+            source_language,
+            target_language,
+        });
         Ok(Worker {
             workspace: workspace.into(),
             // workspace_lock: Mutex::new(0),
@@ -62,10 +152,20 @@ impl Worker {
             outputs: outputs.to_vec(),
             translation_set: Arc::new(Mutex::new(HashMap::new())),
             term_set: Arc::new(Mutex::new(HashMap::new())),
-            translator: Arc::new(Mutex::new(translator)),
+            translator: Arc::new(translator),
+            concurrency,
+            usage: Arc::new(Mutex::new(Usage::default())),
+            dry_run_report: Arc::new(Mutex::new(DryRunReport::default())),
+            skipped: Arc::new(Mutex::new(vec![])),
+            source_language,
+            target_language,
+            font_mapping,
+            reflow,
         })
     }
-    pub fn extract_translate(&self) {
+    /// split and translate outputs, a dry run only counts lines to be translated with estimated
+    /// tokens and cost, see `dry_run_report`, neither templates are written nor requests are sent
+    pub fn extract_translate(&self, dry_run: bool) {
         let progress = Arc::clone(&self.progress);
         let outputs = self.outputs.clone();
         let logger = Arc::clone(&self.logger);
@@ -73,103 +173,273 @@ impl Worker {
         let term_set = Arc::clone(&self.term_set);
         let workspace = self.workspace.to_owned();
         let translator = Arc::clone(&self.translator);
+        let concurrency = self.concurrency.clamp(1, outputs.len().max(1));
+        let source_language = self.source_language;
+        let usage = Arc::clone(&self.usage);
+        *usage.lock().unwrap() = Usage::default();
+        let dry_run_report = Arc::clone(&self.dry_run_report);
+        *dry_run_report.lock().unwrap() = DryRunReport::default();
+        let skipped = Arc::clone(&self.skipped);
+        skipped.lock().unwrap().clear();
         thread::spawn(move || {
-            for (index, output) in outputs.iter().enumerate() {
-                // let mut translator = Translator::new(&self.llm_api_key);
-                let mut sunderer = Sunderer::new(&SundererParam {
-                    source: output.as_path(),
-                    workspace: workspace.as_path(),
-                })
-                .unwrap();
-                let filename = output.file_name().unwrap();
+            // outputs are taken by workers one by one, until all of them are taken
+            let next = AtomicUsize::new(0);
+            let completed = AtomicUsize::new(0);
+            let complete = || {
+                let count = completed.fetch_add(1, Ordering::SeqCst) + 1;
+                let mut progress = progress.lock().unwrap();
+                *progress = count as f64 / outputs.len() as f64;
+            };
+            thread::scope(|scope| {
+                for _ in 0..concurrency {
+                    scope.spawn(|| loop {
+                        let index = next.fetch_add(1, Ordering::SeqCst);
+                        let output = match outputs.get(index) {
+                            Some(output) => output,
+                            None => break,
+                        };
+                        let filename = output.file_name().unwrap_or(output.as_os_str());
+                        logger
+                            .lock()
+                            .unwrap()
+                            .write(&format!(
+                                "Spliting {:?} into cells and template...",
+                                filename
+                            ))
+                            .ok();
+                        let mut sunderer = match split(output, &workspace, dry_run) {
+                            Ok(sunderer) => sunderer,
+                            Err(e) => {
+                                let reason = SkipReason::Split(e);
+                                logger
+                                    .lock()
+                                    .unwrap()
+                                    .warn(&format!("Skipped {:?}, {}", filename, reason))
+                                    .ok();
+                                skipped.lock().unwrap().push((output.to_owned(), reason));
+                                complete();
+                                continue;
+                            }
+                        };
+                        logger
+                            .lock()
+                            .unwrap()
+                            .write(&format!(
+                                "Complete spliting {:?} into cells and template...",
+                                filename
+                            ))
+                            .ok();
+                        if dry_run {
+                            sunderer.estimate(&translator, &mut dry_run_report.lock().unwrap());
+                            complete();
+                            continue;
+                        }
+
+                        logger
+                            .lock()
+                            .unwrap()
+                            .write(&format!("Translating cells of {:?} ...", filename))
+                            .ok();
+                        let mut report = TranslateReport::default();
+                        let cellset = match sunderer.translate(&translator, &mut report) {
+                            Ok(cellset) => cellset,
+                            Err(e) => {
+                                let reason = SkipReason::Translate(format!("{:#}", e));
+                                logger
+                                    .lock()
+                                    .unwrap()
+                                    .warn(&format!("Skipped {:?}, {}", filename, reason))
+                                    .ok();
+                                skipped.lock().unwrap().push((output.to_owned(), reason));
+                                complete();
+                                continue;
+                            }
+                        };
+                        logger
+                            .lock()
+                            .unwrap()
+                            .write(&format!(
+                                "Usage of {:?}: {}",
+                                filename,
+                                describe_usage(&report.usage, translator.cost(&report.usage))
+                            ))
+                            .ok();
+                        *usage.lock().unwrap() += report.usage;
+                        for issue in report.issues {
+                            logger
+                                .lock()
+                                .unwrap()
+                                .warn(&format!(
+                                    "{:?} in {:?} was translated as {:?}, {}",
+                                    issue.source, filename, issue.translation, issue.reason
+                                ))
+                                .ok();
+                        }
+
+                        for (source, translation) in cellset.term_set().iter() {
+                            if source_language.detect(source) {
+                                term_set
+                                    .lock()
+                                    .unwrap()
+                                    .insert(source.to_owned(), translation.to_owned());
+                            }
+                        }
+                        translation_set.lock().unwrap().insert(
+                            sunderer.template_path().to_string_lossy().to_string(),
+                            cellset,
+                        );
+                        logger
+                            .lock()
+                            .unwrap()
+                            .write(&format!("Complete translating cells of {:?}", filename))
+                            .ok();
+                        complete();
+                    });
+                }
+            });
+            if dry_run {
+                let report = dry_run_report.lock().unwrap();
                 logger
                     .lock()
                     .unwrap()
                     .write(&format!(
-                        "Spliting {:?} into cells and template...",
-                        filename
+                        "Dry run of {} outputs: {} unique lines, {} from cache or translation memory, {} by glossary, {} without {}, {} to be translated, {}",
+                        outputs.len(),
+                        report.lines(),
+                        report.cached,
+                        report.glossary,
+                        report.untranslatable,
+                        source_language,
+                        report.misses,
+                        describe_usage(&report.usage, translator.cost(&report.usage))
                     ))
                     .ok();
-                sunderer.split().ok();
+            } else {
+                let usage = *usage.lock().unwrap();
                 logger
                     .lock()
                     .unwrap()
                     .write(&format!(
-                        "Complete spliting {:?} into cells and template...",
-                        filename
+                        "Usage of this run: {}",
+                        describe_usage(&usage, translator.cost(&usage))
                     ))
                     .ok();
-
+            }
+            let skipped = skipped.lock().unwrap().len();
+            if skipped > 0 {
                 logger
                     .lock()
                     .unwrap()
-                    .write(&format!("Translating cells of {:?} ...", filename))
+                    .warn(&format!(
+                        "Skipped {} of {} outputs which could not be split or translated, they are listed above",
+                        skipped,
+                        outputs.len()
+                    ))
                     .ok();
-                let mut translator = translator.lock().unwrap();
-                let cellset = sunderer.translate(&mut translator).unwrap();
-
-                for (source, translation) in cellset.term_set().iter() {
-                    if contains_chinese(source) {
-                        term_set
-                            .lock()
-                            .unwrap()
-                            .insert(source.to_owned(), translation.to_owned());
-                    }
-                }
-                // cellset
-                //     .term_set()
-                //     .into_iter()
-                //     .for_each(|(source, translation)| {
-                //         term_set.lock().unwrap().insert(source, translation);
-                //     });
-                translation_set.lock().unwrap().insert(
-                    sunderer.template_path().to_string_lossy().to_string(),
-                    cellset,
-                );
+            }
+            if let Some(e) = translator.halted() {
                 logger
                     .lock()
                     .unwrap()
-                    .write(&format!("Complete translating cells of {:?}", filename))
+                    .warn(&format!(
+                        "Stopped sending translation requests, {}, lines left untranslated are listed above",
+                        e
+                    ))
                     .ok();
-                let mut progress = progress.lock().unwrap();
-                let current_progress = (index + 1) as f64 / outputs.len() as f64;
-                *progress = current_progress;
             }
         });
     }
     pub fn stuff(&self, alter_translation: &HashMap<String, String>) {
         let alter_translation = alter_translation.clone();
+        let translator = Arc::clone(&self.translator);
         let progress = Arc::clone(&self.progress);
         let translation_set = Arc::clone(&self.translation_set);
         let logger = Arc::clone(&self.logger);
         let destination_dir = self.destination_dir.clone();
+        let source_language = self.source_language;
+        let target_language = self.target_language;
+        let font_mapping = self.font_mapping.clone();
+        let reflow = self.reflow;
         thread::spawn(move || {
+            if let Err(e) = translator.approve(&alter_translation) {
+                logger
+                    .lock()
+                    .unwrap()
+                    .write(&format!("Failed to save reviewed translations: {}", e))
+                    .ok();
+            }
             let translation_set = translation_set.lock().unwrap();
             for (index, (template, rtf_cell_set)) in translation_set.iter().enumerate() {
                 let rtf_cell_set = rtf_cell_set.rebuild(&alter_translation);
                 let template = Path::new(template);
-                let filename = template.file_stem().unwrap();
+                let filename = template.file_stem().unwrap_or(template.as_os_str());
                 let destination = destination_dir.join(filename);
-                let mut stuffer = Stuffer::new(&StufferParam {
-                    template,
-                    destination: &destination,
-                })
-                .unwrap();
                 logger
                     .lock()
                     .unwrap()
                     .write(&format!("Generating translated output {:?} ...", filename))
                     .ok();
-                stuffer.stuff(&rtf_cell_set).unwrap();
-                stuffer.flush().unwrap();
-                logger
-                    .lock()
-                    .unwrap()
-                    .write(&format!(
-                        "Complete generating translated output {:?} ...",
-                        filename
-                    ))
-                    .ok();
+                let generated = Stuffer::new(&StufferParam {
+                    template,
+                    destination: &destination,
+                    source_language,
+                    target_language,
+                    font_mapping: &font_mapping,
+                })
+                .and_then(|mut stuffer| {
+                    stuffer.stuff(&rtf_cell_set)?;
+                    stuffer.flush()
+                });
+                if let Err(e) = &generated {
+                    logger
+                        .lock()
+                        .unwrap()
+                        .warn(&format!(
+                            "Skipped generating translated output {:?}: {}",
+                            filename, e
+                        ))
+                        .ok();
+                } else if reflow {
+                    let logger = logger.lock().unwrap();
+                    match fs::read(&destination)
+                        .map_err(|e| SplitError::io(0, e))
+                        .and_then(|bytes| layout::reflow(&bytes))
+                    {
+                        Ok((bytes, overflows)) => {
+                            if let Err(e) = fs::write(&destination, bytes) {
+                                logger
+                                    .write(&format!("Failed to reflow {:?}: {}", filename, e))
+                                    .ok();
+                            }
+                            for overflow in overflows {
+                                logger
+                                    .warn(&format!(
+                                        "{:?} in {:?} needs {} twips but the column is {} twips wide",
+                                        overflow.text,
+                                        filename,
+                                        overflow.required,
+                                        overflow.available
+                                    ))
+                                    .ok();
+                            }
+                        }
+                        Err(e) => {
+                            logger
+                                .write(&format!("Failed to reflow {:?}: {}", filename, e))
+                                .ok();
+                        }
+                    }
+                }
+                if generated.is_ok() {
+                    logger
+                        .lock()
+                        .unwrap()
+                        .write(&format!(
+                            "Complete generating translated output {:?} ...",
+                            filename
+                        ))
+                        .ok();
+                }
                 let mut progress = progress.lock().unwrap();
                 let current_progress = (index + 1) as f64 / translation_set.len() as f64;
                 *progress = current_progress;
@@ -187,6 +457,31 @@ impl Worker {
         progress
     }
 
+    /// tokens consumed by the latest run of extract_translate
+    pub fn usage(&self) -> Usage {
+        *self.usage.lock().unwrap()
+    }
+
+    /// estimated cost of the latest run, none if price of the model is not configured
+    pub fn cost(&self) -> Option<f64> {
+        self.translator.cost(&self.usage())
+    }
+
+    /// lines counted by the latest dry run of extract_translate
+    pub fn dry_run_report(&self) -> DryRunReport {
+        self.dry_run_report.lock().unwrap().clone()
+    }
+
+    /// outputs of the latest run which could not be split or translated, and why
+    pub fn skipped(&self) -> Vec<(PathBuf, SkipReason)> {
+        self.skipped.lock().unwrap().clone()
+    }
+
+    /// why translation requests were stopped, such as running out of budget
+    pub fn stopped(&self) -> Option<TranslateError> {
+        self.translator.halted()
+    }
+
     pub fn term_set(&self) -> HashMap<String, String> {
         let term_set = Arc::clone(&self.term_set);
         let term_set = term_set.lock().unwrap();
@@ -200,6 +495,30 @@ impl Worker {
     }
 }
 
+fn split(output: &Path, workspace: &Path, dry_run: bool) -> Result<Sunderer, SplitError> {
+    let mut sunderer = Sunderer::new(&SundererParam {
+        source: output,
+        workspace,
+        dry_run,
+    })?;
+    sunderer.split()?;
+    Ok(sunderer)
+}
+
+fn describe_usage(usage: &Usage, cost: Option<f64>) -> String {
+    let cost = match cost {
+        Some(cost) => format!("{:.4}", cost),
+        None => "unknown, price of model is not set".into(),
+    };
+    format!(
+        "{} prompt tokens, {} completion tokens, {} tokens in total, estimated cost {}",
+        usage.prompt_tokens,
+        usage.completion_tokens,
+        usage.total_tokens(),
+        cost
+    )
+}
+
 fn log_file_name() -> String {
     let now = Local::now();
     let filename = format!("{:?}", now);
@@ -208,9 +527,37 @@ fn log_file_name() -> String {
 
 #[cfg(test)]
 mod tests {
-    use std::{env, time::Duration};
+    use std::{
+        env,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        rtf::error::SplitReason,
+        translator::{LlmClient, Passthrough},
+    };
 
     use super::*;
+
+    /// param of worker translating outputs from chinese into english by passthrough, results are
+    /// generated in workspace unless destination_dir is overridden
+    fn param<'a>(workspace: &'a Path, outputs: &'a [PathBuf]) -> WorkerParam<'a> {
+        WorkerParam {
+            workspace,
+            destination_dir: workspace,
+            outputs,
+            backend: Box::new(Passthrough),
+            llm_config: LlmConfig::default(),
+            translation_memory: None,
+            glossary: None,
+            budget: None,
+            source_language: Language::Chinese,
+            target_language: Language::English,
+            font_mapping: None,
+            reflow: false,
+        }
+    }
+
     #[test]
     fn worker_test() -> anyhow::Result<()> {
         let llm_api_key = env::var("LLM_API_KEY")?;
@@ -221,13 +568,12 @@ mod tests {
             r"D:\projects\rusty\playground\rtf\.sample_data\test\t-14-01-03-08-pr-fas.rtf",
         )
         .to_owned()];
-        let worker = Worker::new(&WorkerParam {
-            workspace,
-            outputs: &outputs,
+        let worker = Worker::new(WorkerParam {
             destination_dir,
-            llm_api_key: &llm_api_key,
+            backend: Box::new(LlmClient::new(&llm_api_key)),
+            ..param(workspace, &outputs)
         })?;
-        worker.extract_translate();
+        worker.extract_translate(false);
 
         loop {
             let log = worker.read_log()?;
@@ -239,6 +585,77 @@ mod tests {
             }
             thread::sleep(Duration::from_millis(100));
         }
+        println!("usage: {:?}, cost: {:?}", worker.usage(), worker.cost());
+        Ok(())
+    }
+
+    #[test]
+    fn skip_broken_output_test() -> anyhow::Result<()> {
+        let workspace = env::temp_dir().join("transient_skip_test");
+        fs::create_dir_all(&workspace)?;
+        let broken = workspace.join("t-14-01.rtf");
+        fs::write(&broken, r"{\rtf1{\fonttbl{\f1 SimSun;}}\f1{Male\cell}")?;
+        let valid = workspace.join("t-14-02.rtf");
+        fs::write(&valid, r"{\rtf1{\fonttbl{\f1 SimSun;}}\f1{Male\cell}\row}")?;
+        let outputs = vec![broken.clone(), valid];
+        let worker = Worker::new(WorkerParam {
+            destination_dir: &workspace.join("result"),
+            ..param(&workspace, &outputs)
+        })?;
+        worker.extract_translate(false);
+        let started = Instant::now();
+        while worker.progress() < 1.0 {
+            assert!(
+                started.elapsed() < Duration::from_secs(10),
+                "worker did not complete in 10 seconds"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+        let skipped = worker.skipped();
+        assert_eq!(1, skipped.len());
+        assert_eq!(broken, skipped[0].0);
+        assert_eq!(
+            SkipReason::Split(SplitError::new(0, SplitReason::Unclosed)),
+            skipped[0].1
+        );
+        // logger of worker may be still writing the usage of this run
+        fs::remove_dir_all(&workspace).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn dry_run_test() -> anyhow::Result<()> {
+        let workspace = env::temp_dir().join("transient_dry_run_test");
+        fs::create_dir_all(&workspace)?;
+        let broken = workspace.join("t-14-01.rtf");
+        fs::write(&broken, r"{\rtf1{\fonttbl{\f1 SimSun;}}\f1{Male\cell}")?;
+        let valid = workspace.join("t-14-02.rtf");
+        fs::write(
+            &valid,
+            r"{\rtf1{\fonttbl{\f1 SimSun;}}\f1{男性\cell}\f1{女性\cell}\f1{男性\cell}\row}",
+        )?;
+        let outputs = vec![broken.clone(), valid];
+        let worker = Worker::new(WorkerParam {
+            destination_dir: &workspace.join("result"),
+            ..param(&workspace, &outputs)
+        })?;
+        worker.extract_translate(true);
+        let started = Instant::now();
+        while worker.progress() < 1.0 {
+            assert!(
+                started.elapsed() < Duration::from_secs(10),
+                "worker did not complete in 10 seconds"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+        let report = worker.dry_run_report();
+        assert_eq!(2, report.lines());
+        assert_eq!(2, report.misses);
+        assert_eq!(broken, worker.skipped()[0].0);
+        // neither templates are written nor requests are sent
+        assert!(!workspace.join("t-14-02.rtf.tmp").exists());
+        assert_eq!(Usage::default(), worker.usage());
+        fs::remove_dir_all(&workspace).ok();
         Ok(())
     }
 }
@@ -1,7 +1,24 @@
+mod backend;
+mod budget;
+mod config;
+mod error;
+mod glossary;
+mod limiter;
 mod llm;
+mod mask;
+mod memory;
 mod translator;
 
-pub use translator::Translator;
+pub use backend::{
+    BatchRequest, Completion, Passthrough, TranslateRequest, TranslationBackend, Usage,
+};
+pub use budget::Budget;
+pub use config::{LlmConfig, ModelPrice};
+pub use error::TranslateError;
+pub use glossary::{Glossary, Term};
+pub use llm::LlmClient;
+pub use memory::TranslationMemory;
+pub use translator::{DryRunReport, TranslateReport, Translator, TranslatorParam};
 
 // use std::collections::HashMap;
 
@@ -0,0 +1,129 @@
+use std::{fmt::Debug, ops::AddAssign};
+
+use serde::Deserialize;
+
+use crate::rtf::rtf_cell::CellContext;
+
+use super::{config::LlmConfig, error::TranslateError, glossary::Term};
+
+/// a line to be translated and everything a backend needs to know about it
+#[derive(Debug, Clone, Copy)]
+pub struct TranslateRequest<'a> {
+    pub source: &'a str,
+    /// glossary terms occurring in source, their targets must be used in translation
+    pub terms: &'a [Term],
+    /// table title, column header and row label of the cell source is in
+    pub context: &'a CellContext,
+    /// settings of the current study, backends not talking to a llm may ignore it
+    pub config: &'a LlmConfig,
+}
+
+/// several lines to be translated in one request
+#[derive(Debug, Clone, Copy)]
+pub struct BatchRequest<'a> {
+    pub sources: &'a [String],
+    /// glossary terms occurring in any of sources
+    pub terms: &'a [Term],
+    /// context of each source, in the same order of sources
+    pub contexts: &'a [CellContext],
+    pub config: &'a LlmConfig,
+}
+
+/// tokens consumed by requests
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl Usage {
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, other: Usage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
+}
+
+/// reply of a backend and tokens it consumed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion<T> {
+    pub content: T,
+    pub usage: Usage,
+}
+
+impl<T> Completion<T> {
+    /// a reply which costs nothing, such as one from a rule-only backend
+    pub fn free(content: T) -> Completion<T> {
+        Completion {
+            content,
+            usage: Usage::default(),
+        }
+    }
+}
+
+/// a service which is able to translate one line of text
+///
+/// implement it to plug a new service into `Translator`, such as an OpenAI-compatible endpoint,
+/// a self-hosted model, a rule-only backend or a deterministic fake for tests. transient errors
+/// are retried by `Translator` under its rate limit, so backends return them as they are
+pub trait TranslationBackend: Debug + Send + Sync {
+    fn translate(&self, request: &TranslateRequest) -> Result<Completion<String>, TranslateError>;
+
+    /// translate several lines at once, the result must keep the count and order of sources
+    ///
+    /// lines failed after tokens were consumed keep their errors in the result, so that the usage
+    /// is still charged. translate line by line by default, a `TranslateError::Parse` tells
+    /// caller to fall back to translating line by line as well
+    fn translate_batch(
+        &self,
+        request: &BatchRequest,
+    ) -> Result<Completion<Vec<Result<String, TranslateError>>>, TranslateError> {
+        let mut completion: Completion<Vec<Result<String, TranslateError>>> =
+            Completion::free(vec![]);
+        let empty = CellContext::default();
+        for (index, source) in request.sources.iter().enumerate() {
+            // lines after a failed one fail in the same way, as they would in a batch request
+            if let Some(Err(e)) = completion.content.last() {
+                completion.content.push(Err(e.clone()));
+                continue;
+            }
+            let terms = request
+                .terms
+                .iter()
+                .filter(|term| source.contains(&term.source))
+                .cloned()
+                .collect::<Vec<Term>>();
+            let reply = self.translate(&TranslateRequest {
+                source,
+                terms: &terms,
+                context: request.contexts.get(index).unwrap_or(&empty),
+                config: request.config,
+            });
+            match reply {
+                Ok(reply) => {
+                    completion.content.push(Ok(reply.content));
+                    completion.usage += reply.usage;
+                }
+                Err(e) => completion.content.push(Err(e)),
+            }
+        }
+        Ok(completion)
+    }
+}
+
+/// a rule-only backend, which returns the source as it is
+///
+/// lines not covered by cache or rules in `Translator` will be left untranslated
+#[derive(Debug, Default)]
+pub struct Passthrough;
+
+impl TranslationBackend for Passthrough {
+    fn translate(&self, request: &TranslateRequest) -> Result<Completion<String>, TranslateError> {
+        Ok(Completion::free(request.source.into()))
+    }
+}
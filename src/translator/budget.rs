@@ -0,0 +1,37 @@
+use super::backend::Usage;
+
+/// a cap on spending of a worker, no more request will be sent once it is used up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Budget {
+    /// total tokens of prompts and completions
+    Tokens(u64),
+    /// estimated cost by prices of the model, in the currency of prices
+    Cost(f64),
+}
+
+impl Budget {
+    /// whether usage has reached the budget, a cost budget without price is always exhausted
+    pub fn is_exhausted(&self, usage: &Usage, cost: Option<f64>) -> bool {
+        match self {
+            Budget::Tokens(limit) => usage.total_tokens() >= *limit,
+            Budget::Cost(limit) => cost.is_none_or(|cost| cost >= *limit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn exhausted_test() {
+        let usage = Usage {
+            prompt_tokens: 800,
+            completion_tokens: 200,
+        };
+        assert!(Budget::Tokens(1000).is_exhausted(&usage, None));
+        assert!(!Budget::Tokens(1001).is_exhausted(&usage, None));
+        assert!(Budget::Cost(0.5).is_exhausted(&usage, Some(0.5)));
+        assert!(!Budget::Cost(0.5).is_exhausted(&usage, Some(0.4)));
+        assert!(Budget::Cost(0.5).is_exhausted(&usage, None));
+    }
+}
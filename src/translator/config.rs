@@ -0,0 +1,144 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use super::backend::Usage;
+
+const DEFAULT_BASE_URL: &str = "https://dashscope.aliyuncs.com/compatible-mode/v1";
+const DEFAULT_MODEL: &str = "qwen-turbo";
+const DEFAULT_TEMPERATURE: f64 = 0.8;
+const DEFAULT_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_MAX_RETRIES: u32 = 3;
+const DEFAULT_BACKOFF_MS: u64 = 1000;
+const DEFAULT_BATCH_SIZE: usize = 1;
+const DEFAULT_CONCURRENCY: usize = 1;
+const DEFAULT_SYSTEM_PROMPT: &str = "你是一个资深的临床试验专家，请协助我将下面的{source}翻译成{target}，且不要回复译文以外的内容，比如将中文翻译成英文时，我发送'男性'，你仅需回复Male即可，如果内容包含了类似'[#0]'这样的占位符，请将其原样保留在译文中对应的位置，不要增加、删除或修改占位符";
+
+/// settings of the chat completion request, missing fields fall back to the qwen-turbo defaults
+///
+/// ```toml
+/// base_url = "https://dashscope.aliyuncs.com/compatible-mode/v1"
+/// model = "qwen-plus"
+/// temperature = 0.0
+/// system_prompt = "..."
+/// timeout_secs = 60
+/// max_retries = 3
+/// backoff_ms = 1000
+/// batch_size = 20
+/// concurrency = 4
+/// requests_per_minute = 60
+/// tokens_per_minute = 100000
+///
+/// [prices.qwen-plus]
+/// input = 0.0008
+/// output = 0.002
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LlmConfig {
+    /// base url of an OpenAI-compatible api, "/chat/completions" will be appended
+    pub base_url: String,
+    pub model: String,
+    pub temperature: f64,
+    /// "{source}" and "{target}" will be replaced by names of source and target languages
+    pub system_prompt: String,
+    /// timeout of a single request
+    pub timeout_secs: u64,
+    /// times of retrying on rate limit, timeout and server errors
+    pub max_retries: u32,
+    /// delay before the first retry, doubled on every retry, a random jitter will be added
+    pub backoff_ms: u64,
+    /// max lines sent in one request, lines are sent one by one if it is less than 2
+    pub batch_size: usize,
+    /// outputs translated at the same time, each of them sends one request at a time
+    pub concurrency: usize,
+    /// requests sent to the api in any 60 seconds by all outputs, unlimited if missing or 0
+    pub requests_per_minute: Option<u32>,
+    /// estimated tokens of requests and replies in any 60 seconds, unlimited if missing or 0
+    pub tokens_per_minute: Option<u32>,
+    /// prices of models by name, used to estimate cost of tokens
+    pub prices: HashMap<String, ModelPrice>,
+}
+
+/// price of 1000 tokens, in the currency of your bill
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        LlmConfig {
+            base_url: DEFAULT_BASE_URL.into(),
+            model: DEFAULT_MODEL.into(),
+            temperature: DEFAULT_TEMPERATURE,
+            system_prompt: DEFAULT_SYSTEM_PROMPT.into(),
+            timeout_secs: DEFAULT_TIMEOUT_SECS,
+            max_retries: DEFAULT_MAX_RETRIES,
+            backoff_ms: DEFAULT_BACKOFF_MS,
+            batch_size: DEFAULT_BATCH_SIZE,
+            concurrency: DEFAULT_CONCURRENCY,
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            prices: HashMap::new(),
+        }
+    }
+}
+
+impl LlmConfig {
+    /// load config from a toml file
+    pub fn from_file(filepath: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(filepath)?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    /// estimated cost of usage, none if price of model is not set
+    pub fn cost(&self, usage: &Usage) -> Option<f64> {
+        self.prices.get(&self.model).map(|price| {
+            (usage.prompt_tokens as f64 * price.input
+                + usage.completion_tokens as f64 * price.output)
+                / 1000.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn config_test() {
+        let config: LlmConfig = toml::from_str(
+            r#"
+model = "qwen-plus"
+temperature = 0.0
+"#,
+        )
+        .unwrap();
+        assert_eq!("qwen-plus", config.model);
+        assert_eq!(0.0, config.temperature);
+        assert_eq!(DEFAULT_BASE_URL, config.base_url);
+        assert_eq!(DEFAULT_SYSTEM_PROMPT, config.system_prompt);
+        assert_eq!(DEFAULT_MAX_RETRIES, config.max_retries);
+    }
+
+    #[test]
+    fn cost_test() {
+        let config: LlmConfig = toml::from_str(
+            r#"
+model = "qwen-plus"
+
+[prices.qwen-plus]
+input = 0.8
+output = 2.0
+"#,
+        )
+        .unwrap();
+        let usage = Usage {
+            prompt_tokens: 1500,
+            completion_tokens: 500,
+        };
+        assert_eq!(Some(2.2), config.cost(&usage));
+        assert_eq!(None, LlmConfig::default().cost(&usage));
+    }
+}
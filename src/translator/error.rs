@@ -0,0 +1,74 @@
+use std::{error::Error, fmt::Display};
+
+/// failures of translation backends
+#[derive(Debug, Clone, PartialEq)]
+pub enum TranslateError {
+    /// api key is invalid or has no permission to the model
+    Auth(String),
+    /// account is out of quota or balance
+    Quota(String),
+    /// too many requests in a period
+    RateLimit(String),
+    /// content was rejected by the moderation of provider
+    ContentFilter(String),
+    /// reply could not be understood
+    Parse(String),
+    /// timeout, connection refused and other failures before getting a reply
+    Network(String),
+    /// other unexpected status returned by provider
+    Status { status: u16, message: String },
+    /// spending budget of the worker was used up, no request was sent
+    Budget(String),
+}
+
+impl TranslateError {
+    /// whether the request may succeed by retrying later
+    pub fn is_transient(&self) -> bool {
+        match self {
+            TranslateError::RateLimit(_) | TranslateError::Network(_) => true,
+            TranslateError::Status { status, .. } => *status >= 500,
+            _ => false,
+        }
+    }
+
+    /// whether the following requests will fail as well, no more request should be sent
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            TranslateError::Auth(_) | TranslateError::Quota(_) | TranslateError::Budget(_)
+        )
+    }
+}
+
+impl Display for TranslateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TranslateError::Auth(message) => write!(f, "authentication failed: {}", message),
+            TranslateError::Quota(message) => write!(f, "quota exceeded: {}", message),
+            TranslateError::RateLimit(message) => write!(f, "rate limited: {}", message),
+            TranslateError::ContentFilter(message) => {
+                write!(f, "rejected by content filter: {}", message)
+            }
+            TranslateError::Parse(message) => write!(f, "invalid reply: {}", message),
+            TranslateError::Network(message) => write!(f, "network failure: {}", message),
+            TranslateError::Status { status, message } => {
+                write!(f, "unexpected status {}: {}", status, message)
+            }
+            TranslateError::Budget(message) => write!(f, "budget exhausted: {}", message),
+        }
+    }
+}
+
+impl Error for TranslateError {}
+
+impl From<reqwest::Error> for TranslateError {
+    fn from(e: reqwest::Error) -> Self {
+        TranslateError::Network(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for TranslateError {
+    fn from(e: serde_json::Error) -> Self {
+        TranslateError::Parse(e.to_string())
+    }
+}
@@ -0,0 +1,232 @@
+use std::{cmp::Reverse, collections::HashMap, fs, path::Path};
+
+use regex::Regex;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleKind {
+    /// the whole line equals to source
+    Exact,
+    /// the line starts with source, the rest of line will be kept as it is
+    Prefix,
+    /// source is a regular expression, target could refer to captures such as $1
+    Regex,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GlossaryRule {
+    pub kind: RuleKind,
+    pub source: String,
+    pub target: String,
+}
+
+/// a mandated term pair
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    pub source: String,
+    pub target: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GlossaryFile {
+    #[serde(default)]
+    rule: Vec<GlossaryRule>,
+}
+
+/// terms mandated by sponsor or study, which will be applied before calling translation backend
+///
+/// ```toml
+/// [[rule]]
+/// kind = "exact"
+/// source = "中山康方生物医药有限公司"
+/// target = "Akesobio"
+///
+/// [[rule]]
+/// kind = "prefix"
+/// source = "方案编号："
+/// target = "Protocol: "
+/// ```
+#[derive(Debug, Default)]
+pub struct Glossary {
+    exact: HashMap<String, String>,
+    prefixes: Vec<(String, String)>,
+    patterns: Vec<(Regex, String)>,
+}
+
+impl Glossary {
+    pub fn new(rules: &[GlossaryRule]) -> anyhow::Result<Self> {
+        let mut glossary = Glossary::default();
+        for rule in rules {
+            match rule.kind {
+                RuleKind::Exact => {
+                    glossary
+                        .exact
+                        .insert(rule.source.clone(), rule.target.clone());
+                }
+                RuleKind::Prefix => glossary
+                    .prefixes
+                    .push((rule.source.clone(), rule.target.clone())),
+                RuleKind::Regex => glossary
+                    .patterns
+                    .push((Regex::new(&rule.source)?, rule.target.clone())),
+            }
+        }
+        // the longest prefix wins
+        glossary
+            .prefixes
+            .sort_by_key(|(source, _)| Reverse(source.chars().count()));
+        Ok(glossary)
+    }
+
+    /// load glossary from a toml file
+    pub fn from_file(filepath: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(filepath)?;
+        let file: GlossaryFile = toml::from_str(&content)?;
+        Glossary::new(&file.rule)
+    }
+
+    /// translate line by rules, exact rules go first, then prefix rules and regex rules
+    pub fn translate(&self, line: &str) -> Option<String> {
+        if let Some(target) = self.exact.get(line) {
+            return Some(target.into());
+        }
+        for (source, target) in self.prefixes.iter() {
+            if let Some(rest) = line.strip_prefix(source.as_str()) {
+                return Some(format!("{}{}", target, rest));
+            }
+        }
+        for (re, target) in self.patterns.iter() {
+            if re.is_match(line) {
+                return Some(re.replace(line, target.as_str()).to_string());
+            }
+        }
+        None
+    }
+
+    /// find out terms of exact rules occurring in line, the longer terms go first
+    pub fn terms_in(&self, line: &str) -> Vec<Term> {
+        let mut terms = self
+            .exact
+            .iter()
+            .filter(|(source, _)| line.contains(source.as_str()))
+            .map(|(source, target)| Term {
+                source: source.into(),
+                target: target.into(),
+            })
+            .collect::<Vec<Term>>();
+        terms.sort_by(|a, b| {
+            b.source
+                .chars()
+                .count()
+                .cmp(&a.source.chars().count())
+                .then(a.source.cmp(&b.source))
+        });
+        terms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glossary() -> Glossary {
+        let file: GlossaryFile = toml::from_str(
+            r#"
+[[rule]]
+kind = "exact"
+source = "中山康方生物医药有限公司"
+target = "Akesobio"
+
+[[rule]]
+kind = "exact"
+source = "日期："
+target = "Date:"
+
+[[rule]]
+kind = "prefix"
+source = "方案编号："
+target = "Protocol: "
+
+[[rule]]
+kind = "regex"
+source = '^第 (\{.*?\}) 页 共 (\{.*?\}) 页$'
+target = "Page $1 of $2"
+"#,
+        )
+        .unwrap();
+        Glossary::new(&file.rule).unwrap()
+    }
+
+    #[test]
+    fn glossary_translate_test() {
+        let glossary = glossary();
+        assert_eq!(
+            Some("Akesobio".to_string()),
+            glossary.translate("中山康方生物医药有限公司")
+        );
+        assert_eq!(
+            Some("Protocol: AK112-303".to_string()),
+            glossary.translate("方案编号：AK112-303")
+        );
+        assert_eq!(
+            Some(
+                r"Page {\field{\*\fldinst { PAGE }}} of {\field{\*\fldinst { NUMPAGES }}}"
+                    .to_string()
+            ),
+            glossary.translate(
+                r"第 {\field{\*\fldinst { PAGE }}} 页 共 {\field{\*\fldinst { NUMPAGES }}} 页"
+            )
+        );
+        assert_eq!(None, glossary.translate("男性"));
+    }
+
+    #[test]
+    fn glossary_terms_in_test() {
+        let glossary = glossary();
+        assert_eq!(
+            vec![
+                Term {
+                    source: "中山康方生物医药有限公司".into(),
+                    target: "Akesobio".into()
+                },
+                Term {
+                    source: "日期：".into(),
+                    target: "Date:".into()
+                }
+            ],
+            glossary.terms_in("中山康方生物医药有限公司，日期：2024-01-01")
+        );
+        assert!(glossary.terms_in("男性").is_empty());
+    }
+
+    #[test]
+    fn glossary_example_test() {
+        let filepath = Path::new(env!("CARGO_MANIFEST_DIR")).join("glossary.example.toml");
+        let glossary = Glossary::from_file(&filepath).unwrap();
+        assert_eq!(
+            Some("Akesobio".to_string()),
+            glossary.translate("康方赛诺医药有限公司")
+        );
+        assert_eq!(
+            Some(
+                r"Page {\field{\*\fldinst { PAGE }}} of {\field{\*\fldinst { NUMPAGES }}}"
+                    .to_string()
+            ),
+            glossary.translate(
+                r"第 {\field{\*\fldinst { PAGE }}} 页 共 {\field{\*\fldinst { NUMPAGES }}} 页"
+            )
+        );
+        assert_eq!(
+            Some("Date: 2024-05-29T10:12".to_string()),
+            glossary.translate("日期：2024-05-29T10:12")
+        );
+        assert_eq!(
+            Some("Source: adsl.sas7bdat".to_string()),
+            glossary.translate("来源：adsl.sas7bdat")
+        );
+        assert_eq!(None, glossary.translate("来源：列表 16.2.1"));
+        // labels are not mandated terms of other lines
+        assert!(glossary.terms_in("日期：2024-05-29，输出").is_empty());
+    }
+}
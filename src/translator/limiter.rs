@@ -0,0 +1,205 @@
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use super::{config::LlmConfig, error::TranslateError};
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// limit requests and tokens sent in a sliding window of one minute, shared by all workers
+#[derive(Debug)]
+pub struct RateLimiter {
+    requests_per_minute: Option<u32>,
+    tokens_per_minute: Option<u32>,
+    /// sending time and estimated tokens of requests in current window
+    window: Mutex<VecDeque<(Instant, u32)>>,
+}
+
+impl RateLimiter {
+    /// a limit of 0 is treated as unlimited, as if it is missing
+    pub fn new(requests_per_minute: Option<u32>, tokens_per_minute: Option<u32>) -> Self {
+        RateLimiter {
+            requests_per_minute: requests_per_minute.filter(|limit| *limit > 0),
+            tokens_per_minute: tokens_per_minute.filter(|limit| *limit > 0),
+            window: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// block current thread until a request with estimated tokens is allowed
+    pub fn acquire(&self, tokens: u32) {
+        while let Some(wait) = self.try_acquire(tokens, Instant::now()) {
+            thread::sleep(wait);
+        }
+    }
+
+    /// call until success, a non-transient error or running out of retries, every attempt waits
+    /// for a request with estimated tokens to be allowed
+    pub fn with_retry<T, F>(
+        &self,
+        tokens: u32,
+        config: &LlmConfig,
+        mut call: F,
+    ) -> Result<T, TranslateError>
+    where
+        F: FnMut() -> Result<T, TranslateError>,
+    {
+        let mut attempt = 0;
+        loop {
+            self.acquire(tokens);
+            match call() {
+                Err(e) if e.is_transient() && attempt < config.max_retries => {
+                    thread::sleep(backoff(config.backoff_ms, attempt));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// record the request and return None if it is allowed, otherwise how long to wait
+    fn try_acquire(&self, tokens: u32, now: Instant) -> Option<Duration> {
+        let mut window = self.window.lock().unwrap();
+        while let Some((sent, _)) = window.front() {
+            if now.duration_since(*sent) >= WINDOW {
+                window.pop_front();
+            } else {
+                break;
+            }
+        }
+        let requests_exceeded = self
+            .requests_per_minute
+            .is_some_and(|limit| window.len() as u32 >= limit);
+        // a request larger than the limit is allowed in an empty window, or it will never be sent
+        let tokens_exceeded = self.tokens_per_minute.is_some_and(|limit| {
+            let used = window.iter().map(|(_, tokens)| *tokens).sum::<u32>();
+            !window.is_empty() && used + tokens > limit
+        });
+        if requests_exceeded || tokens_exceeded {
+            if let Some((oldest, _)) = window.front() {
+                return Some(WINDOW.saturating_sub(now.duration_since(*oldest)));
+            }
+        }
+        window.push_back((now, tokens));
+        None
+    }
+}
+
+/// exponential backoff with a random jitter up to half of the delay
+fn backoff(base_ms: u64, attempt: u32) -> Duration {
+    let delay = base_ms.saturating_mul(2u64.saturating_pow(attempt));
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64)
+        .unwrap_or_default();
+    let jitter = if delay < 2 { 0 } else { seed % (delay / 2) };
+    Duration::from_millis(delay + jitter)
+}
+
+/// a rough estimation of tokens, a CJK character is about one token and an english word is
+/// about four characters
+pub fn estimate_tokens(text: &str) -> u32 {
+    let ascii = text.chars().filter(|c| c.is_ascii()).count() as u32;
+    let others = text.chars().count() as u32 - ascii;
+    others + ascii.div_ceil(4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn requests_limit_test() {
+        let limiter = RateLimiter::new(Some(2), None);
+        let now = Instant::now();
+        assert_eq!(None, limiter.try_acquire(10, now));
+        assert_eq!(None, limiter.try_acquire(10, now + Duration::from_secs(10)));
+        assert_eq!(
+            Some(Duration::from_secs(40)),
+            limiter.try_acquire(10, now + Duration::from_secs(20))
+        );
+        assert_eq!(None, limiter.try_acquire(10, now + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn tokens_limit_test() {
+        let limiter = RateLimiter::new(None, Some(100));
+        let now = Instant::now();
+        // larger than limit, but window is empty
+        assert_eq!(None, limiter.try_acquire(150, now));
+        assert_eq!(
+            Some(Duration::from_secs(30)),
+            limiter.try_acquire(10, now + Duration::from_secs(30))
+        );
+        assert_eq!(None, limiter.try_acquire(60, now + Duration::from_secs(60)));
+        assert_eq!(None, limiter.try_acquire(40, now + Duration::from_secs(61)));
+        assert!(limiter
+            .try_acquire(1, now + Duration::from_secs(62))
+            .is_some());
+    }
+
+    #[test]
+    fn zero_limit_test() {
+        // 0 is unlimited, rather than blocking or panicking on an empty window
+        let limiter = RateLimiter::new(Some(0), Some(0));
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(None, limiter.try_acquire(10, now));
+        }
+    }
+
+    #[test]
+    fn retry_test() {
+        let config = LlmConfig {
+            max_retries: 2,
+            backoff_ms: 0,
+            ..Default::default()
+        };
+        let limiter = RateLimiter::new(None, None);
+        let mut calls = 0;
+        let result = limiter.with_retry(10, &config, || {
+            calls += 1;
+            if calls < 3 {
+                Err(TranslateError::RateLimit("slow down".into()))
+            } else {
+                Ok("Male")
+            }
+        });
+        assert_eq!(Ok("Male"), result);
+        // every attempt is recorded in window as a request
+        assert_eq!(3, limiter.window.lock().unwrap().len());
+
+        let mut calls = 0;
+        let result: Result<(), TranslateError> = limiter.with_retry(10, &config, || {
+            calls += 1;
+            Err(TranslateError::Network("timeout".into()))
+        });
+        assert!(result.is_err());
+        assert_eq!(3, calls);
+
+        let mut calls = 0;
+        let result: Result<(), TranslateError> = limiter.with_retry(10, &config, || {
+            calls += 1;
+            Err(TranslateError::Auth("invalid api key".into()))
+        });
+        assert!(result.is_err());
+        assert_eq!(1, calls);
+    }
+
+    #[test]
+    fn backoff_test() {
+        for attempt in 0..4 {
+            let delay = backoff(100, attempt).as_millis() as u64;
+            let base = 100 * 2u64.pow(attempt);
+            assert!(delay >= base && delay < base + base / 2);
+        }
+    }
+
+    #[test]
+    fn estimate_tokens_test() {
+        assert_eq!(2, estimate_tokens("男性"));
+        assert_eq!(3, estimate_tokens("Male (N=198)"));
+        assert_eq!(0, estimate_tokens(""));
+    }
+}
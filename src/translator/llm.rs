@@ -1,8 +1,17 @@
-use std::error::Error;
+use std::time::Duration;
 
 use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
 use serde::{Deserialize, Serialize};
 
+use crate::rtf::rtf_cell::CellContext;
+
+use super::{
+    backend::{BatchRequest, Completion, TranslateRequest, TranslationBackend, Usage},
+    config::LlmConfig,
+    error::TranslateError,
+    glossary::Term,
+};
+
 #[derive(Debug, Serialize)]
 struct RequestBody {
     pub model: String,
@@ -23,6 +32,7 @@ struct Reply {
     pub model: String,
     pub created: u32,
     pub object: String,
+    #[serde(default)]
     pub usage: Usage,
     pub choices: Vec<Choice>,
 }
@@ -36,44 +46,318 @@ struct Choice {
     pub logprobs: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+struct ErrorReply {
+    pub error: ErrorDetail,
+}
+
 #[allow(unused)]
 #[derive(Debug, Deserialize)]
-struct Usage {
-    pub prompt_tokens: u32,
-    pub completion_tokens: u32,
-    pub total_tokens: u32,
+struct ErrorDetail {
+    pub message: String,
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default, rename = "type")]
+    pub kind: Option<String>,
+}
+
+const BATCH_PROMPT: &str = "下面是一个JSON数组，请逐项翻译其中的text，并仅回复一个JSON数组，数组长度、顺序和id必须与原数组一致，例如发送[{\"id\":0,\"text\":\"男性\"}]，你仅需回复[{\"id\":0,\"text\":\"Male\"}]，context是该项在表格中的上下文，仅供参考，不要翻译也不要回复";
+
+const CONTEXT_PROMPT: &str = "待翻译的内容位于表格中，以下上下文仅供参考，不要翻译也不要回复：";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BatchItem {
+    pub id: usize,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+}
+
+/// client of an OpenAI-compatible chat completion api, such as DashScope
+#[derive(Debug)]
+pub struct LlmClient {
+    api_key: String,
+    client: reqwest::blocking::Client,
 }
 
-pub struct TranslateParam<'a> {
-    pub source: &'a str,
-    pub api_key: &'a str,
+impl LlmClient {
+    pub fn new(api_key: &str) -> LlmClient {
+        LlmClient {
+            api_key: api_key.into(),
+            client: reqwest::blocking::Client::new(),
+        }
+    }
+
+    /// send one chat completion request, return content of the first choice
+    fn send(
+        &self,
+        config: &LlmConfig,
+        system: &str,
+        user: &str,
+    ) -> Result<Completion<String>, TranslateError> {
+        let request_body = RequestBody {
+            model: config.model.clone(),
+            messages: vec![
+                Message {
+                    role: "system".into(),
+                    content: system.into(),
+                },
+                Message {
+                    role: "user".into(),
+                    content: user.into(),
+                },
+            ],
+            temperature: config.temperature,
+        };
+        let body = serde_json::to_vec(&request_body)?;
+        let result = self
+            .client
+            .post(format!(
+                "{}/chat/completions",
+                config.base_url.trim_end_matches('/')
+            ))
+            .header(AUTHORIZATION, format!("Bearer {}", self.api_key))
+            .header(CONTENT_TYPE, "application/json")
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .body(body)
+            .send()?;
+        let status = result.status().as_u16();
+        let bytes = result.bytes()?;
+        if status != 200 {
+            return Err(classify(status, &bytes));
+        }
+        let reply = serde_json::from_slice::<Reply>(&bytes)?;
+        match reply.choices.into_iter().next() {
+            Some(choice) if choice.finish_reason.eq("content_filter") => Err(
+                TranslateError::ContentFilter(format!("{:?} was filtered", user)),
+            ),
+            Some(choice) => Ok(Completion {
+                content: choice.message.content,
+                usage: reply.usage,
+            }),
+            None => Err(TranslateError::Parse("no choice in reply".into())),
+        }
+    }
+}
+
+impl TranslationBackend for LlmClient {
+    fn translate(&self, request: &TranslateRequest) -> Result<Completion<String>, TranslateError> {
+        let TranslateRequest {
+            source,
+            terms,
+            context,
+            config,
+        } = *request;
+        let system = match describe(context) {
+            Some(context) => format!(
+                "{}\n{}{}",
+                system_prompt(&config.system_prompt, terms),
+                CONTEXT_PROMPT,
+                context
+            ),
+            None => system_prompt(&config.system_prompt, terms),
+        };
+        self.send(config, &system, source)
+    }
+
+    fn translate_batch(
+        &self,
+        request: &BatchRequest,
+    ) -> Result<Completion<Vec<Result<String, TranslateError>>>, TranslateError> {
+        let BatchRequest {
+            sources,
+            terms,
+            contexts,
+            config,
+        } = *request;
+        let system = format!(
+            "{}\n{}",
+            system_prompt(&config.system_prompt, terms),
+            BATCH_PROMPT
+        );
+        let items = sources
+            .iter()
+            .enumerate()
+            .map(|(id, text)| BatchItem {
+                id,
+                text: text.clone(),
+                context: contexts.get(id).and_then(describe),
+            })
+            .collect::<Vec<BatchItem>>();
+        let user = serde_json::to_string(&items)?;
+        let reply = self.send(config, &system, &user)?;
+        // tokens were consumed even if the reply could not be parsed
+        let content = match parse_batch(&reply.content, sources.len()) {
+            Ok(lines) => lines.into_iter().map(Ok).collect(),
+            Err(e) => vec![Err(e); sources.len()],
+        };
+        Ok(Completion {
+            content,
+            usage: reply.usage,
+        })
+    }
 }
 
-pub fn translate(param: TranslateParam) -> Result<String, Box<dyn Error>> {
-    let TranslateParam { source, api_key } = param;
-    let client = reqwest::blocking::Client::new();
-    let request_body = RequestBody {
-        model: "qwen-turbo".into(),
-        messages: vec![
-            Message {
-                role: "system".into(),
-                content: "你是一个资深的临床试验专家，请协助我将下面的中文翻译成英文，且不要回复答案以外的内容，比如我发送'男性'，你仅需回复Male即可，如果内容包含了类似'{\\uc0\\u12288 }'这种被花括号包裹起来的，里面是类似unicode字符声明的内容，则无需翻译这部分内容，直接保留在原文中即可".into(),
+/// parse reply of a batch request, items must be in the same count and order as sources
+fn parse_batch(reply: &str, count: usize) -> Result<Vec<String>, TranslateError> {
+    // reply may be wrapped in a markdown code block
+    let (start, end) = match (reply.find('['), reply.rfind(']')) {
+        (Some(start), Some(end)) if start < end => (start, end + 1),
+        _ => return Err(TranslateError::Parse("no json array in batch reply".into())),
+    };
+    let items = serde_json::from_str::<Vec<BatchItem>>(&reply[start..end])?;
+    if items.len() != count {
+        return Err(TranslateError::Parse(format!(
+            "expect {} items in batch reply, but got {}",
+            count,
+            items.len()
+        )));
+    }
+    items
+        .into_iter()
+        .enumerate()
+        .map(|(index, item)| {
+            if item.id == index {
+                Ok(item.text)
+            } else {
+                Err(TranslateError::Parse(format!(
+                    "expect item {} in batch reply, but got {}",
+                    index, item.id
+                )))
+            }
+        })
+        .collect()
+}
+
+/// turn a failed reply into error by its status and error code
+fn classify(status: u16, body: &[u8]) -> TranslateError {
+    let (message, code) = match serde_json::from_slice::<ErrorReply>(body) {
+        Ok(reply) => (
+            reply.error.message,
+            reply.error.code.or(reply.error.kind).unwrap_or_default(),
+        ),
+        Err(_) => (String::from_utf8_lossy(body).to_string(), "".into()),
+    };
+    let code = code.to_lowercase();
+    match status {
+        401 | 403 => TranslateError::Auth(message),
+        429 if code.contains("quota") || code.contains("arrearage") => {
+            TranslateError::Quota(message)
+        }
+        429 => TranslateError::RateLimit(message),
+        400 if code.contains("inspection") || code.contains("content_filter") => {
+            TranslateError::ContentFilter(message)
+        }
+        _ => TranslateError::Status { status, message },
+    }
+}
+
+/// describe context of a cell in one line, none if nothing is known about it
+fn describe(context: &CellContext) -> Option<String> {
+    let parts = [
+        ("表格标题", &context.title),
+        ("所在列", &context.column),
+        ("所在行", &context.row),
+    ]
+    .iter()
+    .filter_map(|(name, value)| value.as_ref().map(|value| format!("{}：{}", name, value)))
+    .collect::<Vec<String>>();
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join("；"))
+    }
+}
+
+/// append the required term pairs to system prompt
+fn system_prompt(prompt: &str, terms: &[Term]) -> String {
+    if terms.is_empty() {
+        return prompt.into();
+    }
+    let pairs = terms
+        .iter()
+        .map(|term| format!("{} => {}", term.source, term.target))
+        .collect::<Vec<String>>()
+        .join("\n");
+    format!("{}\n翻译时必须使用以下术语：\n{}", prompt, pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn system_prompt_test() {
+        assert_eq!("prompt", system_prompt("prompt", &[]));
+        let terms = vec![
+            Term {
+                source: "受试者".into(),
+                target: "Subject".into(),
             },
-            Message {
-                role: "user".into(),
-                content: source.into(),
+            Term {
+                source: "不良事件".into(),
+                target: "Adverse Event".into(),
             },
-        ],
-        temperature: 0.8,
-    };
-    let body = serde_json::to_vec(&request_body)?;
-    let result = client
-        .post("https://dashscope.aliyuncs.com/compatible-mode/v1/chat/completions")
-        .header(AUTHORIZATION, format!("Bearer {}", api_key))
-        .header(CONTENT_TYPE, "application/json")
-        .body(body)
-        .send()?;
-    let bytes = result.bytes()?;
-    let reply = serde_json::from_slice::<Reply>(&bytes)?;
-    Ok(reply.choices[0].message.content.clone())
+        ];
+        assert_eq!(
+            "prompt\n翻译时必须使用以下术语：\n受试者 => Subject\n不良事件 => Adverse Event",
+            system_prompt("prompt", &terms)
+        );
+    }
+
+    #[test]
+    fn describe_test() {
+        assert_eq!(None, describe(&CellContext::default()));
+        let context = CellContext {
+            title: Some("表 14.1 人口学资料".into()),
+            column: Some("试验组".into()),
+            row: None,
+            ..Default::default()
+        };
+        assert_eq!(
+            Some("表格标题：表 14.1 人口学资料；所在列：试验组".to_string()),
+            describe(&context)
+        );
+    }
+
+    #[test]
+    fn classify_test() {
+        let body = br#"{"error":{"message":"Incorrect API key provided.","type":"invalid_request_error","code":"invalid_api_key"}}"#;
+        assert_eq!(
+            TranslateError::Auth("Incorrect API key provided.".into()),
+            classify(401, body)
+        );
+        let body = br#"{"error":{"message":"You exceeded your current quota","type":"insufficient_quota"}}"#;
+        assert!(matches!(classify(429, body), TranslateError::Quota(_)));
+        let body =
+            br#"{"error":{"message":"Requests rate limit exceeded","code":"limit_requests"}}"#;
+        assert!(matches!(classify(429, body), TranslateError::RateLimit(_)));
+        let body = br#"{"error":{"message":"Input data may contain inappropriate content.","code":"data_inspection_failed"}}"#;
+        assert!(matches!(
+            classify(400, body),
+            TranslateError::ContentFilter(_)
+        ));
+        let error = classify(502, b"Bad Gateway");
+        assert_eq!(
+            TranslateError::Status {
+                status: 502,
+                message: "Bad Gateway".into()
+            },
+            error
+        );
+        assert!(error.is_transient());
+    }
+
+    #[test]
+    fn parse_batch_test() {
+        let reply =
+            "```json\n[{\"id\":0,\"text\":\"Male\"},{\"id\":1,\"text\":\"[#0]Female\"}]\n```";
+        assert_eq!(
+            vec!["Male".to_string(), "[#0]Female".to_string()],
+            parse_batch(reply, 2).unwrap()
+        );
+        assert!(parse_batch(reply, 3).is_err());
+        let reply = r#"[{"id":1,"text":"Female"},{"id":0,"text":"Male"}]"#;
+        assert!(parse_batch(reply, 2).is_err());
+        assert!(parse_batch("Male\nFemale", 2).is_err());
+    }
 }
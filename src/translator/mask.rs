@@ -0,0 +1,294 @@
+use regex::Regex;
+
+use crate::utils::Language;
+
+/// a line in which rtf control words and groups were replaced by opaque tokens, such as "[#0]"
+///
+/// groups without text of source language are masked as a whole, such as "{\super a}" and field
+/// groups, while the other groups only get their braces and control words masked, so that the
+/// text inside could still be translated
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaskedLine {
+    pub text: String,
+    tokens: Vec<String>,
+}
+
+impl MaskedLine {
+    pub fn new(line: &str, language: Language) -> Self {
+        let mut masker = Masker {
+            language,
+            text: String::new(),
+            tokens: vec![],
+            pending: String::new(),
+        };
+        masker.walk(line);
+        masker.flush();
+        MaskedLine {
+            text: masker.text,
+            tokens: masker.tokens,
+        }
+    }
+
+    /// restore tokens in translation, every token must occur exactly once
+    pub fn unmask(&self, translation: &str) -> anyhow::Result<String> {
+        let re = Regex::new(r"\[#(\d+)\]")?;
+        let mut occurrences = vec![0; self.tokens.len()];
+        for caps in re.captures_iter(translation) {
+            match caps[1].parse::<usize>() {
+                Ok(index) if index < self.tokens.len() => occurrences[index] += 1,
+                _ => anyhow::bail!("unknown token {} in translation", &caps[0]),
+            }
+        }
+        for (index, count) in occurrences.iter().enumerate() {
+            match count {
+                0 => anyhow::bail!("token {} is missing in translation", token(index)),
+                1 => {}
+                _ => anyhow::bail!("token {} is duplicated in translation", token(index)),
+            }
+        }
+        Ok(re
+            .replace_all(translation, |caps: &regex::Captures| {
+                self.tokens[caps[1].parse::<usize>().unwrap()].clone()
+            })
+            .to_string())
+    }
+}
+
+fn token(index: usize) -> String {
+    format!("[#{}]", index)
+}
+
+struct Masker {
+    /// source language
+    language: Language,
+    text: String,
+    tokens: Vec<String>,
+    /// control sequences not yet turned into a token, adjacent ones share the same token
+    pending: String,
+}
+
+impl Masker {
+    fn walk(&mut self, source: &str) {
+        let bytes = source.as_bytes();
+        let mut cursor = 0;
+        while cursor < bytes.len() {
+            match bytes[cursor] {
+                b'{' => {
+                    let end = match group_end(bytes, cursor) {
+                        Some(end) => end,
+                        None => {
+                            // unbalanced group, keep the rest untouched
+                            self.pending.push_str(&source[cursor..]);
+                            return;
+                        }
+                    };
+                    let group = &source[cursor..end];
+                    if self.language.detect(group) {
+                        self.pending.push('{');
+                        self.walk(&source[cursor + 1..end - 1]);
+                        self.pending.push('}');
+                    } else {
+                        self.pending.push_str(group);
+                    }
+                    cursor = end;
+                }
+                b'\\' => {
+                    let end = control_end(bytes, cursor);
+                    self.pending.push_str(&source[cursor..end]);
+                    cursor = end;
+                }
+                _ => {
+                    let c = source[cursor..].chars().next().unwrap();
+                    if c.is_whitespace() && !c.is_ascii() {
+                        // indents such as the ideographic space are kept out of translation
+                        self.pending.push(c);
+                    } else {
+                        self.flush();
+                        self.text.push(c);
+                    }
+                    cursor += c.len_utf8();
+                }
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        self.text.push_str(&token(self.tokens.len()));
+        self.tokens.push(std::mem::take(&mut self.pending));
+    }
+}
+
+/// end (exclusive) of the group starting at cursor, escaped braces are skipped
+fn group_end(bytes: &[u8], cursor: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut cursor = cursor;
+    while cursor < bytes.len() {
+        match bytes[cursor] {
+            b'\\' => cursor += 1,
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(cursor + 1);
+                }
+            }
+            _ => {}
+        }
+        cursor += 1;
+    }
+    None
+}
+
+/// end (exclusive) of the control word or control symbol starting at cursor
+///
+/// a control word owns its numeric parameter and one space as delimiter, such as "\u12288 "
+fn control_end(bytes: &[u8], cursor: usize) -> usize {
+    let mut end = cursor + 1;
+    match bytes.get(end) {
+        Some(c) if c.is_ascii_alphabetic() => {
+            while end < bytes.len() && bytes[end].is_ascii_alphabetic() {
+                end += 1;
+            }
+            if end < bytes.len() && bytes[end] == b'-' {
+                end += 1;
+            }
+            while end < bytes.len() && bytes[end].is_ascii_digit() {
+                end += 1;
+            }
+            if end < bytes.len() && bytes[end] == b' ' {
+                end += 1;
+            }
+            end
+        }
+        // hex escape, such as \'b0
+        Some(b'\'') => (end + 3).min(bytes.len()),
+        Some(_) => end + 1,
+        None => end,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        sync::{Arc, Mutex},
+    };
+
+    use crate::{
+        rtf::{
+            rtf_cell::RtfCell,
+            sunderer::{Sunderer, SundererParam},
+        },
+        translator::{
+            backend::{Completion, TranslateRequest, TranslationBackend},
+            error::TranslateError,
+            Glossary, LlmConfig, TranslateReport, TranslationMemory, Translator, TranslatorParam,
+        },
+    };
+
+    use super::*;
+    #[test]
+    fn mask_test() {
+        let line = r"{\uc0\u12288 }{\uc0\u12288 }男性{\super a}";
+        let masked = MaskedLine::new(line, Language::Chinese);
+        assert_eq!("[#0]男性[#1]", masked.text);
+        assert_eq!(line, masked.unmask("[#0]男性[#1]").unwrap());
+        assert_eq!(
+            r"{\uc0\u12288 }{\uc0\u12288 }Male{\super a}",
+            masked.unmask("[#0]Male[#1]").unwrap()
+        );
+
+        let line = r"第 {\field{\*\fldinst { PAGE }}} 页 {\b 合计}\i 例";
+        let masked = MaskedLine::new(line, Language::Chinese);
+        assert_eq!("第 [#0] 页 [#1]合计[#2]例", masked.text);
+        assert_eq!(
+            r"Page {\field{\*\fldinst { PAGE }}} {\b Total}\i n",
+            masked.unmask("Page [#0] [#1]Total[#2]n").unwrap()
+        );
+
+        // ideographic spaces decoded from rtf are masked as well
+        let masked = MaskedLine::new("\u{3000}\u{3000}男性", Language::Chinese);
+        assert_eq!("[#0]男性", masked.text);
+        assert_eq!("\u{3000}\u{3000}Male", masked.unmask("[#0]Male").unwrap());
+
+        let masked = MaskedLine::new("男性", Language::Chinese);
+        assert!(masked.tokens.is_empty());
+        assert_eq!("Male", masked.unmask("Male").unwrap());
+    }
+
+    #[test]
+    fn mask_decoded_test() -> anyhow::Result<()> {
+        #[derive(Debug)]
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+        impl TranslationBackend for Recorder {
+            fn translate(
+                &self,
+                request: &TranslateRequest,
+            ) -> Result<Completion<String>, TranslateError> {
+                self.0.lock().unwrap().push(request.source.into());
+                Ok(Completion::free(request.source.replace("男性", "Male")))
+            }
+        }
+        // cells are decoded by sunderer before masking, indents must still be protected
+        let workspace = env::temp_dir().join("transient_mask_test");
+        fs::create_dir_all(&workspace)?;
+        let source = workspace.join("t-14-01.rtf");
+        let indent = format!(r"{{\uc0\u{} }}", 12288);
+        fs::write(
+            &source,
+            format!(
+                r"{{\rtf1{{\fonttbl{{\f1 SimSun;}}}}\trowd\cellx1000\cellx2000\f1{{{0}{0}男性\cell}}\f1{{{0}{0}12 (5.0)\cell}}\row}}",
+                indent
+            ),
+        )?;
+        let mut sunderer = Sunderer::new(&SundererParam {
+            source: &source,
+            workspace: &workspace,
+            dry_run: false,
+        })?;
+        let sources = Arc::new(Mutex::new(vec![]));
+        let translator = Translator::new(TranslatorParam {
+            backend: Box::new(Recorder(Arc::clone(&sources))),
+            config: LlmConfig::default(),
+            memory: TranslationMemory::default(),
+            glossary: Glossary::default(),
+            budget: None,
+            source_language: Language::Chinese,
+            target_language: Language::English,
+        });
+        let cell_set = sunderer
+            .split()?
+            .translate(&translator, &mut TranslateReport::default())?;
+        let translated = (0..cell_set.size())
+            .filter_map(|id| match cell_set.find(id) {
+                Some(RtfCell::General(cell)) => Some(cell.translated_lines),
+                _ => None,
+            })
+            .collect::<Vec<Vec<String>>>();
+        let indent = format!("{{{}}}", indent);
+        assert_eq!(
+            vec![
+                vec![format!("{0}{0}Male", indent)],
+                vec![format!("{0}{0}12 (5.0)", indent)]
+            ],
+            translated
+        );
+        // only the text was sent, the number was kept as it is
+        assert_eq!(vec!["[#0]男性".to_string()], *sources.lock().unwrap());
+        fs::remove_dir_all(&workspace).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn unmask_failure_test() {
+        let masked = MaskedLine::new(r"{\super a}男性{\super b}", Language::Chinese);
+        assert_eq!("[#0]男性[#1]", masked.text);
+        assert!(masked.unmask("[#0]Male").is_err());
+        assert!(masked.unmask("[#0]Male[#1][#1]").is_err());
+        assert!(masked.unmask("[#0]Male[#1][#2]").is_err());
+        assert!(masked.unmask("[#1]Male[#0]").is_ok());
+    }
+}
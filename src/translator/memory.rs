@@ -0,0 +1,302 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{rtf::rtf_cell::CellRole, utils::Language};
+
+/// source, role, source language and target language of an entry
+type Key = (String, Option<CellRole>, Option<Language>, Option<Language>);
+
+/// a translation record in translation memory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryEntry {
+    pub source: String,
+    pub target: String,
+    pub model: String,
+    pub timestamp: String,
+    /// whether the translation has been reviewed by a human
+    pub approved: bool,
+    /// role of the cells the source was found in, none for any role, such as reviewed lines or
+    /// entries recorded before roles
+    #[serde(default)]
+    pub role: Option<CellRole>,
+    /// language pair of the translation, none for entries recorded before language pairs, which
+    /// are never used as the pair is unknown
+    #[serde(default)]
+    pub source_language: Option<Language>,
+    #[serde(default)]
+    pub target_language: Option<Language>,
+}
+
+impl MemoryEntry {
+    pub fn new(
+        source: &str,
+        target: &str,
+        model: &str,
+        approved: bool,
+        source_language: Language,
+        target_language: Language,
+    ) -> Self {
+        MemoryEntry {
+            source: source.into(),
+            target: target.into(),
+            model: model.into(),
+            timestamp: chrono::Local::now().to_rfc3339(),
+            approved,
+            role: None,
+            source_language: Some(source_language),
+            target_language: Some(target_language),
+        }
+    }
+
+    fn key(&self) -> Key {
+        (
+            self.source.clone(),
+            self.role,
+            self.source_language,
+            self.target_language,
+        )
+    }
+}
+
+/// translations of previous runs, stored as json lines so it could be shared across runs and studies
+///
+/// an approved entry will never be replaced by an unapproved one, entries of other language pairs
+/// are kept in the file but never returned
+#[derive(Debug, Default)]
+pub struct TranslationMemory {
+    filepath: Option<PathBuf>,
+    entries: HashMap<Key, MemoryEntry>,
+    /// lines of the file which could not be parsed, such as a line truncated by a crash
+    malformed: usize,
+    /// the file does not end with a line break, which is written before the next entry
+    unterminated: bool,
+}
+
+impl TranslationMemory {
+    /// open a translation memory on disk, the file will be created when the first entry is inserted
+    ///
+    /// malformed lines are skipped and counted, see `malformed`
+    pub fn open(filepath: &Path) -> anyhow::Result<Self> {
+        let mut memory = TranslationMemory {
+            filepath: Some(filepath.into()),
+            ..Default::default()
+        };
+        if filepath.exists() {
+            let content = fs::read_to_string(filepath)?;
+            memory.unterminated = !content.is_empty() && !content.ends_with('\n');
+            for line in content.lines() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str(line) {
+                    Ok(entry) => {
+                        memory.merge(entry);
+                    }
+                    Err(_) => memory.malformed += 1,
+                }
+            }
+        }
+        Ok(memory)
+    }
+
+    /// number of lines skipped by open as they could not be parsed
+    pub fn malformed(&self) -> usize {
+        self.malformed
+    }
+
+    /// entry of source in role translated between the languages, an approved entry for any role
+    /// goes ahead of an unapproved one
+    pub fn get(
+        &self,
+        source: &str,
+        role: CellRole,
+        source_language: Language,
+        target_language: Language,
+    ) -> Option<&MemoryEntry> {
+        let key = |role| {
+            (
+                source.to_string(),
+                role,
+                Some(source_language),
+                Some(target_language),
+            )
+        };
+        let specific = self.entries.get(&key(Some(role)));
+        let any = self.entries.get(&key(None));
+        match (specific, any) {
+            (Some(specific), Some(any)) if any.approved && !specific.approved => Some(any),
+            (Some(specific), _) => Some(specific),
+            (None, any) => any,
+        }
+    }
+
+    /// add entry into memory and append it to the file on disk
+    pub fn insert(&mut self, entry: MemoryEntry) -> anyhow::Result<()> {
+        if !self.merge(entry.clone()) {
+            return Ok(());
+        }
+        if let Some(filepath) = &self.filepath {
+            let mut writer = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(filepath)?;
+            if self.unterminated {
+                writer.write_all(b"\n")?;
+                self.unterminated = false;
+            }
+            writer.write_all(format!("{}\n", serde_json::to_string(&entry)?).as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// return false if entry was rejected because of an approved one existing
+    fn merge(&mut self, entry: MemoryEntry) -> bool {
+        let key = entry.key();
+        if let Some(existing) = self.entries.get(&key) {
+            if existing.approved && !entry.approved {
+                return false;
+            }
+        }
+        self.entries.insert(key, entry);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    /// an entry translated from chinese into english
+    fn entry(source: &str, target: &str, model: &str, approved: bool) -> MemoryEntry {
+        MemoryEntry::new(
+            source,
+            target,
+            model,
+            approved,
+            Language::Chinese,
+            Language::English,
+        )
+    }
+
+    #[test]
+    fn memory_test() -> anyhow::Result<()> {
+        let filepath =
+            env::temp_dir().join(format!("transient-memory-{}.jsonl", std::process::id()));
+        if filepath.exists() {
+            fs::remove_file(&filepath)?;
+        }
+        let mut memory = TranslationMemory::open(&filepath)?;
+        memory.insert(entry("男性", "Male", "qwen-turbo", false))?;
+        memory.insert(entry("女性", "Woman", "qwen-turbo", false))?;
+        memory.insert(entry("女性", "Female", "human", true))?;
+        memory.insert(entry("女性", "Lady", "qwen-turbo", false))?;
+        memory.insert(MemoryEntry {
+            role: Some(CellRole::Header),
+            ..entry("例", "n", "qwen-turbo", false)
+        })?;
+        memory.insert(MemoryEntry {
+            role: Some(CellRole::Label),
+            ..entry("女性", "Females", "qwen-turbo", false)
+        })?;
+
+        let memory = TranslationMemory::open(&filepath)?;
+        let (zh, en) = (Language::Chinese, Language::English);
+        assert_eq!(4, memory.entries.len());
+        assert_eq!(
+            "Male",
+            memory.get("男性", CellRole::Value, zh, en).unwrap().target
+        );
+        let female = memory.get("女性", CellRole::Label, zh, en).unwrap();
+        assert_eq!("Female", female.target);
+        assert!(female.approved);
+        assert_eq!(
+            "n",
+            memory.get("例", CellRole::Header, zh, en).unwrap().target
+        );
+        assert!(memory.get("例", CellRole::Value, zh, en).is_none());
+        fs::remove_file(&filepath)?;
+        Ok(())
+    }
+
+    #[test]
+    fn memory_language_test() -> anyhow::Result<()> {
+        let filepath = env::temp_dir().join(format!(
+            "transient-memory-language-{}.jsonl",
+            std::process::id()
+        ));
+        // an entry recorded before language pairs
+        fs::write(
+            &filepath,
+            r#"{"source":"合计","target":"Total","model":"human","timestamp":"","approved":true}
+"#,
+        )?;
+        let mut memory = TranslationMemory::open(&filepath)?;
+        memory.insert(entry("男性", "Male", "qwen-turbo", false))?;
+        memory.insert(MemoryEntry::new(
+            "男性",
+            "男性",
+            "qwen-turbo",
+            false,
+            Language::Chinese,
+            Language::Japanese,
+        ))?;
+
+        let memory = TranslationMemory::open(&filepath)?;
+        let (zh, en, ja) = (Language::Chinese, Language::English, Language::Japanese);
+        assert_eq!(
+            "Male",
+            memory.get("男性", CellRole::Text, zh, en).unwrap().target
+        );
+        assert_eq!(
+            "男性",
+            memory.get("男性", CellRole::Text, zh, ja).unwrap().target
+        );
+        assert!(memory.get("男性", CellRole::Text, en, zh).is_none());
+        assert!(memory.get("合计", CellRole::Text, zh, en).is_none());
+        fs::remove_file(&filepath)?;
+        Ok(())
+    }
+
+    #[test]
+    fn memory_truncated_test() -> anyhow::Result<()> {
+        let filepath = env::temp_dir().join(format!(
+            "transient-memory-truncated-{}.jsonl",
+            std::process::id()
+        ));
+        if filepath.exists() {
+            fs::remove_file(&filepath)?;
+        }
+        let mut memory = TranslationMemory::open(&filepath)?;
+        memory.insert(entry("男性", "Male", "qwen-turbo", false))?;
+        // the last line was cut off, such as by a crash while appending it
+        let mut writer = OpenOptions::new().append(true).open(&filepath)?;
+        writer.write_all(r#"{"source":"女性","target":"Fem"#.as_bytes())?;
+
+        let mut memory = TranslationMemory::open(&filepath)?;
+        let (zh, en) = (Language::Chinese, Language::English);
+        assert_eq!(1, memory.malformed());
+        assert_eq!(
+            "Male",
+            memory.get("男性", CellRole::Text, zh, en).unwrap().target
+        );
+        assert!(memory.get("女性", CellRole::Text, zh, en).is_none());
+        // entries inserted later are not glued to the truncated line
+        memory.insert(entry("女性", "Female", "qwen-turbo", false))?;
+        let memory = TranslationMemory::open(&filepath)?;
+        assert_eq!(1, memory.malformed());
+        assert_eq!(
+            "Female",
+            memory.get("女性", CellRole::Text, zh, en).unwrap().target
+        );
+        fs::remove_file(&filepath)?;
+        Ok(())
+    }
+}
@@ -1,80 +1,1114 @@
-use std::collections::HashMap;
-
-use regex::Regex;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Mutex, RwLock},
+};
 
 use crate::{
-    rtf::rtf_cell::{GeneralCell, RtfCell},
-    utils::contains_chinese,
+    rtf::{
+        processor::escape_text,
+        rtf_cell::{CellContext, CellRole, GeneralCell, RtfCell},
+    },
+    utils::Language,
 };
 
-use super::llm::{self, TranslateParam};
+use super::{
+    backend::{BatchRequest, TranslateRequest, TranslationBackend, Usage},
+    budget::Budget,
+    config::LlmConfig,
+    error::TranslateError,
+    glossary::{Glossary, Term},
+    limiter::{estimate_tokens, RateLimiter},
+    mask::MaskedLine,
+    memory::{MemoryEntry, TranslationMemory},
+};
+
+pub struct TranslatorParam {
+    pub backend: Box<dyn TranslationBackend>,
+    pub config: LlmConfig,
+    pub memory: TranslationMemory,
+    pub glossary: Glossary,
+    /// no more request will be sent once it is used up
+    pub budget: Option<Budget>,
+    /// lines without text of source language are kept as they are
+    pub source_language: Language,
+    pub target_language: Language,
+}
+
+/// a line which was translated, but needs a review by human
+#[derive(Debug, Clone)]
+pub struct TranslationIssue {
+    pub source: String,
+    pub translation: String,
+    pub reason: String,
+}
+
+/// what happened while translating cells of an output
+#[derive(Debug, Default)]
+pub struct TranslateReport {
+    pub issues: Vec<TranslationIssue>,
+    /// tokens consumed by requests sent for these cells
+    pub usage: Usage,
+}
+
+/// lines of a dry run counted by where their translations come from, each line is counted once
+#[derive(Debug, Default, Clone)]
+pub struct DryRunReport {
+    /// found in cache or translation memory
+    pub cached: usize,
+    /// translated by glossary rules
+    pub glossary: usize,
+    /// lines without text of source language, which are kept as they are
+    pub untranslatable: usize,
+    /// lines to be sent to backend
+    pub misses: usize,
+    /// estimated tokens of translating the misses
+    pub usage: Usage,
+    seen: HashSet<(String, CellRole)>,
+}
+
+impl DryRunReport {
+    /// unique non-empty lines, a line found in cells of different roles is counted once per role
+    pub fn lines(&self) -> usize {
+        self.seen.len()
+    }
+}
+
+/// where a translation found without calling backend comes from
+enum Hit {
+    Cache,
+    Glossary,
+    Untranslatable,
+}
 
+/// translator shared by workers, cache and translation memory are safe for concurrent use
 #[derive(Debug)]
 pub struct Translator {
-    cache: HashMap<String, String>,
-    api_key: String,
+    cache: RwLock<HashMap<(String, CellRole), String>>,
+    memory: Mutex<TranslationMemory>,
+    glossary: Glossary,
+    source_language: Language,
+    target_language: Language,
+    backend: Box<dyn TranslationBackend>,
+    config: LlmConfig,
+    limiter: RateLimiter,
+    budget: Option<Budget>,
+    /// tokens consumed by all requests sent by this translator
+    spent: Mutex<Usage>,
+    /// a fatal error of backend, such as invalid api key, no more request will be sent
+    halted: Mutex<Option<TranslateError>>,
 }
 
 impl Translator {
-    pub fn new(api_key: &str) -> Translator {
-        let mut cache = HashMap::new();
-        cache.insert("中山康方生物医药有限公司".into(), "Akesobio".into());
-        cache.insert("康方赛诺医药有限公司".into(), "Akesobio".into());
-        cache.insert(
-            r"第 {\field{\*\fldinst { PAGE }}} 页 共 {\field{\*\fldinst { NUMPAGES }}} 页".into(),
-            "Page Of ".into(),
-        );
+    pub fn new(param: TranslatorParam) -> Translator {
+        let TranslatorParam {
+            backend,
+            config,
+            memory,
+            glossary,
+            budget,
+            source_language,
+            target_language,
+        } = param;
+        let config = LlmConfig {
+            system_prompt: config
+                .system_prompt
+                .replace("{source}", source_language.name())
+                .replace("{target}", target_language.name()),
+            ..config
+        };
+        let limiter = RateLimiter::new(config.requests_per_minute, config.tokens_per_minute);
         Translator {
-            cache,
-            api_key: api_key.into(),
-        }
-    }
-    pub fn translate(&mut self, rtf_cell: &RtfCell) -> RtfCell {
-        match rtf_cell {
-            RtfCell::General(cell) => {
-                let mut translated_lines = vec![];
-                cell.lines.iter().for_each(|line| {
-                    let line = line.trim();
-                    if line.is_empty() {
-                        translated_lines.push("".into());
-                    } else {
-                        if let Some(data) = self.cache.get(line) {
-                            translated_lines.push(data.into());
-                            return;
-                        }
-
-                        let re = Regex::new(r"^方案编号：").unwrap();
-
-                        // let result = llm::translate(line).unwrap();
-                        // println!("{} - {}", line, result);
-                        let result = if !contains_chinese(line) {
-                            line.to_owned()
-                        } else if re.is_match(line) {
-                            line.replace("方案编号：", "Protocol: ")
-                        } else {
-                            llm::translate(TranslateParam {
-                                source: &line,
-                                api_key: &self.api_key,
-                            })
-                            .unwrap()
-                        };
-                        self.cache.insert(line.to_string(), result.clone());
-                        translated_lines.push(result);
+            cache: RwLock::new(HashMap::new()),
+            memory: Mutex::new(memory),
+            glossary,
+            source_language,
+            target_language,
+            backend,
+            config,
+            limiter,
+            budget,
+            spent: Mutex::new(Usage::default()),
+            halted: Mutex::new(None),
+        }
+    }
+    pub fn translate(&self, rtf_cell: &RtfCell, report: &mut TranslateReport) -> RtfCell {
+        rtf_cell.map_general(|cell| GeneralCell {
+            lines: cell.lines.clone(),
+            translated_lines: cell
+                .lines
+                .iter()
+                .map(|line| self.translate_line(line.trim(), &cell.context, report))
+                .collect(),
+            styles: cell.styles.clone(),
+            context: cell.context.clone(),
+        })
+    }
+
+    /// translate cells, lines missing in cache will be sent in batches if batch size is set
+    ///
+    /// a line is translated in the context of the first cell it was found in, the translation is
+    /// reused by the same line in other cells of the same role, such as column headers
+    pub fn translate_all(
+        &self,
+        rtf_cells: &[RtfCell],
+        report: &mut TranslateReport,
+    ) -> Vec<RtfCell> {
+        if self.config.batch_size > 1 {
+            let mut pending: Vec<(String, CellContext)> = vec![];
+            for cell in rtf_cells.iter().map(|cell| cell.general()) {
+                for line in cell.lines.iter().map(|line| line.trim()) {
+                    let role = cell.context.role;
+                    if self.lookup(line, role).is_none()
+                        && !pending
+                            .iter()
+                            .any(|(p, context)| p.eq(line) && context.role.eq(&role))
+                    {
+                        pending.push((line.into(), cell.context.clone()));
+                    }
+                }
+            }
+            for chunk in pending.chunks(self.config.batch_size) {
+                self.translate_batch(chunk, report);
+            }
+        }
+        rtf_cells
+            .iter()
+            .map(|cell| self.translate(cell, report))
+            .collect()
+    }
+
+    /// count lines of cells which would be sent to backend, no request will be sent
+    pub fn estimate(&self, rtf_cells: &[RtfCell], report: &mut DryRunReport) {
+        for cell in rtf_cells.iter().map(|cell| cell.general()) {
+            for line in cell.lines.iter().map(|line| line.trim()) {
+                let role = cell.context.role;
+                if line.is_empty() || !report.seen.insert((line.into(), role)) {
+                    continue;
+                }
+                match self.lookup_hit(line, role) {
+                    Some((Hit::Cache, _)) => report.cached += 1,
+                    Some((Hit::Glossary, _)) => report.glossary += 1,
+                    Some((Hit::Untranslatable, _)) => report.untranslatable += 1,
+                    None => {
+                        report.misses += 1;
+                        report.usage += self.estimate_usage(line);
+                    }
+                }
+            }
+        }
+    }
+
+    /// translation found without calling backend
+    fn lookup(&self, line: &str, role: CellRole) -> Option<String> {
+        self.lookup_hit(line, role).map(|(_, data)| data)
+    }
+
+    /// translation found without calling backend and where it was found
+    fn lookup_hit(&self, line: &str, role: CellRole) -> Option<(Hit, String)> {
+        if line.is_empty() {
+            return Some((Hit::Untranslatable, "".into()));
+        }
+        if let Some(data) = self.cache.read().unwrap().get(&(line.into(), role)) {
+            return Some((Hit::Cache, data.into()));
+        }
+        // mandated terms go ahead of translation memory
+        if let Some(data) = self.glossary.translate(line) {
+            return Some((Hit::Glossary, data));
+        }
+        if let Some(entry) =
+            self.memory
+                .lock()
+                .unwrap()
+                .get(line, role, self.source_language, self.target_language)
+        {
+            return Some((Hit::Cache, entry.target.clone()));
+        }
+        if !self.source_language.detect(line) {
+            return Some((Hit::Untranslatable, line.into()));
+        }
+        None
+    }
+
+    fn translate_line(
+        &self,
+        line: &str,
+        context: &CellContext,
+        report: &mut TranslateReport,
+    ) -> String {
+        if let Some(data) = self.lookup(line, context.role) {
+            return data;
+        }
+        self.check_budget();
+        if let Some(e) = self.halted.lock().unwrap().as_ref() {
+            report.issues.push(TranslationIssue {
+                source: line.into(),
+                translation: "".into(),
+                reason: format!("skipped, {}", e),
+            });
+            return line.into();
+        }
+
+        let terms = self.glossary.terms_in(line);
+        let masked = MaskedLine::new(line, self.source_language);
+        let reply = self
+            .limiter
+            .with_retry(self.estimate_request(&masked.text), &self.config, || {
+                self.backend.translate(&TranslateRequest {
+                    source: &masked.text,
+                    terms: &terms,
+                    context,
+                    config: &self.config,
+                })
+            })
+            .map(|completion| {
+                self.charge(completion.usage, report);
+                completion.content
+            });
+        self.accept(line, context.role, &masked, &terms, reply, report)
+    }
+
+    /// send lines in one request, lines will be left to `translate_line` if batch reply could
+    /// not be parsed or was rejected by content filter, tokens consumed are charged anyway
+    fn translate_batch(&self, pending: &[(String, CellContext)], report: &mut TranslateReport) {
+        self.check_budget();
+        if self.halted.lock().unwrap().is_some() {
+            return;
+        }
+        let (lines, contexts): (Vec<String>, Vec<CellContext>) = pending.iter().cloned().unzip();
+        let masked = lines
+            .iter()
+            .map(|line| MaskedLine::new(line, self.source_language))
+            .collect::<Vec<MaskedLine>>();
+        let sources = masked
+            .iter()
+            .map(|masked| masked.text.clone())
+            .collect::<Vec<String>>();
+        let mut terms: Vec<Term> = vec![];
+        for line in lines.iter() {
+            for term in self.glossary.terms_in(line) {
+                if !terms.contains(&term) {
+                    terms.push(term);
+                }
+            }
+        }
+        let replies = self.limiter.with_retry(
+            self.estimate_request(&sources.concat()),
+            &self.config,
+            || {
+                self.backend.translate_batch(&BatchRequest {
+                    sources: &sources,
+                    terms: &terms,
+                    contexts: &contexts,
+                    config: &self.config,
+                })
+            },
+        );
+        match replies {
+            Ok(replies) => {
+                self.charge(replies.usage, report);
+                for (((line, context), masked), reply) in
+                    pending.iter().zip(masked.iter()).zip(replies.content)
+                {
+                    if matches!(
+                        reply,
+                        Err(TranslateError::Parse(_)) | Err(TranslateError::ContentFilter(_))
+                    ) {
+                        continue;
                     }
+                    let terms = self.glossary.terms_in(line);
+                    self.accept(line, context.role, masked, &terms, reply, report);
+                }
+            }
+            Err(TranslateError::Parse(_)) | Err(TranslateError::ContentFilter(_)) => {}
+            Err(e) => {
+                for ((line, context), masked) in pending.iter().zip(masked.iter()) {
+                    self.accept(line, context.role, masked, &[], Err(e.clone()), report);
+                }
+            }
+        }
+    }
+
+    /// check reply of backend, a line failed will be left untranslated in the rest of this run
+    fn accept(
+        &self,
+        line: &str,
+        role: CellRole,
+        masked: &MaskedLine,
+        terms: &[Term],
+        reply: Result<String, TranslateError>,
+        report: &mut TranslateReport,
+    ) -> String {
+        let reply = match reply {
+            Ok(reply) => reply,
+            Err(e) => {
+                // leave the line untranslated, so that the rest of cells could go on
+                report.issues.push(TranslationIssue {
+                    source: line.into(),
+                    translation: "".into(),
+                    reason: e.to_string(),
+                });
+                if e.is_fatal() {
+                    *self.halted.lock().unwrap() = Some(e);
+                }
+                self.cache
+                    .write()
+                    .unwrap()
+                    .insert((line.into(), role), line.into());
+                return line.into();
+            }
+        };
+        // reply is plain text, tokens in which will be restored to rtf after escaping
+        let result = match masked.unmask(&escape_text(&reply)) {
+            Ok(result) => result,
+            Err(e) => {
+                // rtf control words were broken by backend, keep the source to avoid corrupt rtf
+                report.issues.push(TranslationIssue {
+                    source: line.into(),
+                    translation: reply,
+                    reason: e.to_string(),
                 });
-                RtfCell::General(GeneralCell {
-                    lines: cell.lines.clone(),
-                    translated_lines,
-                    styles: cell.styles.clone(),
+                self.cache
+                    .write()
+                    .unwrap()
+                    .insert((line.into(), role), line.into());
+                return line.into();
+            }
+        };
+        let missing = missing_terms(&result, terms);
+        if missing.is_empty() {
+            self.memory
+                .lock()
+                .unwrap()
+                .insert(MemoryEntry {
+                    role: Some(role),
+                    ..MemoryEntry::new(
+                        line,
+                        &result,
+                        &self.config.model,
+                        false,
+                        self.source_language,
+                        self.target_language,
+                    )
+                })
+                .ok();
+        } else {
+            // keep the translation for this run only, it will be retried in the next run
+            report.issues.push(TranslationIssue {
+                source: line.into(),
+                translation: result.clone(),
+                reason: format!("missing mandated terms: {}", missing.join(", ")),
+            });
+        }
+        self.cache
+            .write()
+            .unwrap()
+            .insert((line.into(), role), result.clone());
+        result
+    }
+
+    /// tokens of system prompt, source and a translation about twice the size of source
+    fn estimate_request(&self, source: &str) -> u32 {
+        estimate_tokens(&self.config.system_prompt) + estimate_tokens(source) * 3
+    }
+
+    /// usage of translating a line, system prompt is shared by lines in a batch
+    fn estimate_usage(&self, line: &str) -> Usage {
+        let source = estimate_tokens(&MaskedLine::new(line, self.source_language).text) as u64;
+        let prompt = estimate_tokens(&self.config.system_prompt) as u64;
+        Usage {
+            prompt_tokens: prompt / self.config.batch_size.max(1) as u64 + source,
+            completion_tokens: source * 2,
+        }
+    }
+
+    /// record tokens consumed by a request
+    fn charge(&self, usage: Usage, report: &mut TranslateReport) {
+        report.usage += usage;
+        *self.spent.lock().unwrap() += usage;
+    }
+
+    /// halt if budget was used up, requests being sent by other workers may exceed it slightly
+    fn check_budget(&self) {
+        let budget = match &self.budget {
+            Some(budget) => budget,
+            None => return,
+        };
+        let spent = *self.spent.lock().unwrap();
+        if !budget.is_exhausted(&spent, self.cost(&spent)) {
+            return;
+        }
+        let mut halted = self.halted.lock().unwrap();
+        if halted.is_none() {
+            *halted = Some(TranslateError::Budget(format!(
+                "{} tokens were used, budget is {:?}",
+                spent.total_tokens(),
+                budget
+            )));
+        }
+    }
+
+    /// the fatal error which stopped sending requests, such as invalid api key or budget
+    pub fn halted(&self) -> Option<TranslateError> {
+        self.halted.lock().unwrap().clone()
+    }
+
+    /// estimated cost of usage by prices of current model
+    pub fn cost(&self, usage: &Usage) -> Option<f64> {
+        self.config.cost(usage)
+    }
+
+    /// record translations reviewed by human into translation memory, they are used in any role
+    pub fn approve(&self, reviewed: &HashMap<String, String>) -> anyhow::Result<()> {
+        let mut memory = self.memory.lock().unwrap();
+        for (source, target) in reviewed {
+            // drop translations of this run, so that the reviewed one is found in memory
+            self.cache
+                .write()
+                .unwrap()
+                .retain(|(line, _), _| line.ne(source));
+            memory.insert(MemoryEntry::new(
+                source,
+                target,
+                "human",
+                true,
+                self.source_language,
+                self.target_language,
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+/// targets of terms which are not found in translation, case insensitive
+fn missing_terms(translation: &str, terms: &[Term]) -> Vec<String> {
+    let translation = translation.to_lowercase();
+    terms
+        .iter()
+        .filter(|term| !translation.contains(&term.target.to_lowercase()))
+        .map(|term| term.target.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::translator::{
+        backend::Completion,
+        glossary::{GlossaryRule, RuleKind},
+    };
+
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct Fake {
+        calls: AtomicUsize,
+    }
+
+    impl TranslationBackend for Fake {
+        fn translate(
+            &self,
+            request: &TranslateRequest,
+        ) -> Result<Completion<String>, TranslateError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            // honour the first term only
+            let source = match request.terms.first() {
+                Some(term) => request.source.replace(&term.source, &term.target),
+                None => request.source.into(),
+            };
+            Ok(Completion {
+                content: format!("{}:{}", request.config.model, source),
+                usage: Usage {
+                    prompt_tokens: 10,
+                    completion_tokens: 5,
+                },
+            })
+        }
+    }
+
+    /// param of translator from chinese into english with defaults, tests override what they need
+    fn param(backend: Box<dyn TranslationBackend>) -> TranslatorParam {
+        TranslatorParam {
+            backend,
+            config: LlmConfig::default(),
+            memory: TranslationMemory::default(),
+            glossary: Glossary::default(),
+            budget: None,
+            source_language: Language::Chinese,
+            target_language: Language::English,
+        }
+    }
+
+    fn translator() -> Translator {
+        Translator::new(TranslatorParam {
+            config: LlmConfig {
+                model: "fake".into(),
+                ..Default::default()
+            },
+            glossary: Glossary::new(&[
+                GlossaryRule {
+                    kind: RuleKind::Prefix,
+                    source: "方案编号：".into(),
+                    target: "Protocol: ".into(),
+                },
+                GlossaryRule {
+                    kind: RuleKind::Exact,
+                    source: "受试者".into(),
+                    target: "Subject".into(),
+                },
+                GlossaryRule {
+                    kind: RuleKind::Exact,
+                    source: "不良事件".into(),
+                    target: "Adverse Event".into(),
+                },
+            ])
+            .unwrap(),
+            ..param(Box::<Fake>::default())
+        })
+    }
+
+    fn translated_lines(cell: RtfCell) -> Vec<String> {
+        cell.general().translated_lines.clone()
+    }
+
+    #[test]
+    fn translate_with_backend_test() {
+        let translator = translator();
+        let mut report = TranslateReport::default();
+        let cell = RtfCell::General(GeneralCell {
+            lines: vec![
+                "男性".into(),
+                "".into(),
+                "(N=198)".into(),
+                "方案编号：AK112".into(),
+            ],
+            translated_lines: vec![],
+            styles: "".into(),
+            context: CellContext::default(),
+        });
+        assert_eq!(
+            vec!["fake:男性", "", "(N=198)", "Protocol: AK112"],
+            translated_lines(translator.translate(&cell, &mut report))
+        );
+        // only 男性 was sent to backend, the rest are cached in this run
+        translator.translate(&cell, &mut report);
+        assert_eq!(
+            Usage {
+                prompt_tokens: 10,
+                completion_tokens: 5
+            },
+            report.usage
+        );
+    }
+
+    #[test]
+    fn translate_footnote_test() {
+        let translator = translator();
+        let mut report = TranslateReport::default();
+        let cell = RtfCell::FootNote(GeneralCell {
+            lines: vec![
+                "受试者".into(),
+                r"注：见第{\field{\*\fldinst PAGE}{\fldrslt 1}}页".into(),
+            ],
+            translated_lines: vec![],
+            styles: "".into(),
+            context: CellContext::default(),
+        });
+        // footnotes go through glossary and backend, field codes are kept
+        assert_eq!(
+            vec![
+                "Subject",
+                r"fake:注：见第{\field{\*\fldinst PAGE}{\fldrslt 1}}页"
+            ],
+            translated_lines(translator.translate(&cell, &mut report))
+        );
+    }
+
+    #[test]
+    fn translate_with_memory_test() {
+        let translator = translator();
+        translator
+            .approve(&HashMap::from([("女性".to_string(), "Female".to_string())]))
+            .unwrap();
+        let mut report = TranslateReport::default();
+        let cell = RtfCell::General(GeneralCell {
+            lines: vec!["女性".into(), "男性".into()],
+            translated_lines: vec![],
+            styles: "".into(),
+            context: CellContext::default(),
+        });
+        assert_eq!(
+            vec!["Female", "fake:男性"],
+            translated_lines(translator.translate(&cell, &mut report))
+        );
+        assert_eq!(
+            "fake:男性",
+            translator
+                .memory
+                .lock()
+                .unwrap()
+                .get("男性", CellRole::Text, Language::Chinese, Language::English)
+                .unwrap()
+                .target
+        );
+        assert_eq!(
+            "fake",
+            translator
+                .memory
+                .lock()
+                .unwrap()
+                .get("男性", CellRole::Text, Language::Chinese, Language::English)
+                .unwrap()
+                .model
+        );
+    }
+
+    #[test]
+    fn translate_with_terms_test() {
+        let translator = translator();
+        let mut report = TranslateReport::default();
+        let cell = RtfCell::General(GeneralCell {
+            lines: vec!["受试者分布".into(), "受试者不良事件".into()],
+            translated_lines: vec![],
+            styles: "".into(),
+            context: CellContext::default(),
+        });
+        assert_eq!(
+            vec!["fake:Subject分布", "fake:受试者Adverse Event"],
+            translated_lines(translator.translate(&cell, &mut report))
+        );
+        let issues = report.issues;
+        assert_eq!(1, issues.len());
+        assert_eq!("受试者不良事件", issues[0].source);
+        assert!(issues[0].reason.contains("Subject"));
+        assert!(translator
+            .memory
+            .lock()
+            .unwrap()
+            .get(
+                "受试者不良事件",
+                CellRole::Text,
+                Language::Chinese,
+                Language::English
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn translate_with_mask_test() {
+        #[derive(Debug)]
+        struct Dropper;
+        impl TranslationBackend for Dropper {
+            fn translate(
+                &self,
+                request: &TranslateRequest,
+            ) -> Result<Completion<String>, TranslateError> {
+                Ok(Completion::free(
+                    request.source.replace("[#1]", "").replace("男性", "Male"),
+                ))
+            }
+        }
+        let translator = Translator::new(param(Box::new(Dropper)));
+        let mut report = TranslateReport::default();
+        let cell = RtfCell::General(GeneralCell {
+            lines: vec![r"{\super a}男性".into(), r"{\super a}男性{\super b}".into()],
+            translated_lines: vec![],
+            styles: "".into(),
+            context: CellContext::default(),
+        });
+        assert_eq!(
+            vec![r"{\super a}Male", r"{\super a}男性{\super b}"],
+            translated_lines(translator.translate(&cell, &mut report))
+        );
+        let issues = report.issues;
+        assert_eq!(1, issues.len());
+        assert!(issues[0].reason.contains("[#1]"));
+        assert!(translator
+            .memory
+            .lock()
+            .unwrap()
+            .get(
+                r"{\super a}男性{\super b}",
+                CellRole::Text,
+                Language::Chinese,
+                Language::English
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn translate_escape_test() {
+        #[derive(Debug)]
+        struct Braces;
+        impl TranslationBackend for Braces {
+            fn translate(
+                &self,
+                request: &TranslateRequest,
+            ) -> Result<Completion<String>, TranslateError> {
+                Ok(Completion::free(request.source.replace("集合", "{set}\\")))
+            }
+        }
+        let translator = Translator::new(param(Box::new(Braces)));
+        let mut report = TranslateReport::default();
+        let cell = RtfCell::General(GeneralCell {
+            lines: vec![r"{\super a}集合".into()],
+            translated_lines: vec![],
+            styles: "".into(),
+            context: CellContext::default(),
+        });
+        assert_eq!(
+            vec![r"{\super a}\{set\}\\"],
+            translated_lines(translator.translate(&cell, &mut report))
+        );
+    }
+
+    #[test]
+    fn translate_with_failure_test() {
+        #[derive(Debug, Default)]
+        struct Failure {
+            calls: AtomicUsize,
+        }
+        impl TranslationBackend for Failure {
+            fn translate(
+                &self,
+                request: &TranslateRequest,
+            ) -> Result<Completion<String>, TranslateError> {
+                match self.calls.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(TranslateError::ContentFilter(request.source.into())),
+                    _ => Err(TranslateError::Auth("invalid api key".into())),
+                }
+            }
+        }
+        let translator = Translator::new(param(Box::<Failure>::default()));
+        let mut report = TranslateReport::default();
+        let cell = RtfCell::General(GeneralCell {
+            lines: vec!["男性".into(), "女性".into(), "合计".into()],
+            translated_lines: vec![],
+            styles: "".into(),
+            context: CellContext::default(),
+        });
+        assert_eq!(
+            vec!["男性", "女性", "合计"],
+            translated_lines(translator.translate(&cell, &mut report))
+        );
+        let issues = report.issues;
+        assert_eq!(3, issues.len());
+        assert!(issues[0].reason.contains("content filter"));
+        assert!(issues[1].reason.contains("authentication"));
+        assert!(issues[2].reason.starts_with("skipped"));
+    }
+
+    #[test]
+    fn translate_all_test() {
+        static BATCHES: AtomicUsize = AtomicUsize::new(0);
+        static LINES: AtomicUsize = AtomicUsize::new(0);
+        #[derive(Debug)]
+        struct Batch;
+        impl TranslationBackend for Batch {
+            fn translate(
+                &self,
+                request: &TranslateRequest,
+            ) -> Result<Completion<String>, TranslateError> {
+                LINES.fetch_add(1, Ordering::SeqCst);
+                Ok(Completion::free(format!("line:{}", request.source)))
+            }
+            fn translate_batch(
+                &self,
+                request: &BatchRequest,
+            ) -> Result<Completion<Vec<Result<String, TranslateError>>>, TranslateError>
+            {
+                BATCHES.fetch_add(1, Ordering::SeqCst);
+                if request.sources.iter().any(|source| source.contains("错误")) {
+                    // tokens of a broken reply are consumed as well
+                    return Ok(Completion {
+                        content: vec![
+                            Err(TranslateError::Parse("broken reply".into()));
+                            request.sources.len()
+                        ],
+                        usage: Usage {
+                            prompt_tokens: 10,
+                            completion_tokens: 5,
+                        },
+                    });
+                }
+                Ok(Completion::free(
+                    request
+                        .sources
+                        .iter()
+                        .map(|source| Ok(format!("batch:{}", source)))
+                        .collect(),
+                ))
+            }
+        }
+        let translator = Translator::new(TranslatorParam {
+            config: LlmConfig {
+                batch_size: 2,
+                ..Default::default()
+            },
+            ..param(Box::new(Batch))
+        });
+        let mut report = TranslateReport::default();
+        let cells = vec![
+            RtfCell::General(GeneralCell {
+                lines: vec!["男性".into(), "女性".into(), "(N=198)".into()],
+                translated_lines: vec![],
+                styles: "".into(),
+                context: CellContext::default(),
+            }),
+            RtfCell::General(GeneralCell {
+                lines: vec!["男性".into(), "错误".into(), "合计".into()],
+                translated_lines: vec![],
+                styles: "".into(),
+                context: CellContext::default(),
+            }),
+        ];
+        let cells = translator.translate_all(&cells, &mut report);
+        assert_eq!(
+            vec!["batch:男性", "batch:女性", "(N=198)"],
+            translated_lines(cells[0].clone())
+        );
+        assert_eq!(
+            vec!["batch:男性", "line:错误", "line:合计"],
+            translated_lines(cells[1].clone())
+        );
+        // [男性, 女性] and [错误, 合计] which falls back to line by line
+        assert_eq!(2, BATCHES.load(Ordering::SeqCst));
+        assert_eq!(2, LINES.load(Ordering::SeqCst));
+        assert_eq!(15, report.usage.total_tokens());
+    }
+
+    #[test]
+    fn translate_batch_by_line_test() {
+        #[derive(Debug)]
+        struct Flaky;
+        impl TranslationBackend for Flaky {
+            fn translate(
+                &self,
+                request: &TranslateRequest,
+            ) -> Result<Completion<String>, TranslateError> {
+                if request.source == "错误" {
+                    return Err(TranslateError::Status {
+                        status: 400,
+                        message: "bad request".into(),
+                    });
+                }
+                Ok(Completion {
+                    content: format!("line:{}", request.source),
+                    usage: Usage {
+                        prompt_tokens: 10,
+                        completion_tokens: 5,
+                    },
                 })
             }
-            RtfCell::FootNote(source) => RtfCell::FootNote(
-                source
-                    .replace(r"\uc0\u26085 \uc0\u26399 \uc0\u65306", "Date:")
-                    .replace(r"\uc0\u26469 \uc0\u28304 \uc0\u65306", "Source:")
-                    .replace(r"\uc0\u65292", ", ")
-                    .replace(r"\uc0\u36755 \uc0\u20986", "Output"),
-            ),
         }
+        let sources = vec!["男性".to_string(), "错误".into(), "合计".into()];
+        let reply = Flaky
+            .translate_batch(&BatchRequest {
+                sources: &sources,
+                terms: &[],
+                contexts: &[],
+                config: &LlmConfig::default(),
+            })
+            .unwrap();
+        // usage of lines before the failed one is kept
+        assert_eq!(15, reply.usage.total_tokens());
+        assert_eq!(Ok("line:男性".to_string()), reply.content[0]);
+        assert!(reply.content[1..].iter().all(|line| line.is_err()));
+    }
+
+    #[test]
+    fn translate_concurrently_test() {
+        let translator = translator();
+        let cell = RtfCell::General(GeneralCell {
+            lines: vec!["男性".into(), "受试者".into()],
+            translated_lines: vec![],
+            styles: "".into(),
+            context: CellContext::default(),
+        });
+        let reports = std::thread::scope(|scope| {
+            let workers = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut report = TranslateReport::default();
+                        let lines = translated_lines(translator.translate(&cell, &mut report));
+                        assert_eq!(vec!["fake:男性", "Subject"], lines);
+                        report
+                    })
+                })
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .collect::<Vec<TranslateReport>>()
+        });
+        assert!(reports.iter().all(|report| report.issues.is_empty()));
+        assert_eq!(1, translator.cache.read().unwrap().len());
+    }
+
+    #[test]
+    fn translate_with_budget_test() {
+        let translator = Translator::new(TranslatorParam {
+            config: LlmConfig {
+                model: "fake".into(),
+                ..Default::default()
+            },
+            // a request of Fake costs 15 tokens
+            budget: Some(Budget::Tokens(30)),
+            ..param(Box::<Fake>::default())
+        });
+        let mut report = TranslateReport::default();
+        let cell = RtfCell::General(GeneralCell {
+            lines: vec![
+                "男性".into(),
+                "女性".into(),
+                "合计".into(),
+                "(N=198)".into(),
+            ],
+            translated_lines: vec![],
+            styles: "".into(),
+            context: CellContext::default(),
+        });
+        assert_eq!(
+            vec!["fake:男性", "fake:女性", "合计", "(N=198)"],
+            translated_lines(translator.translate(&cell, &mut report))
+        );
+        assert_eq!(30, report.usage.total_tokens());
+        assert_eq!(1, report.issues.len());
+        assert!(report.issues[0]
+            .reason
+            .starts_with("skipped, budget exhausted"));
+        assert!(matches!(
+            translator.halted(),
+            Some(TranslateError::Budget(_))
+        ));
+    }
+
+    #[test]
+    fn estimate_test() {
+        let translator = translator();
+        translator
+            .approve(&HashMap::from([("女性".to_string(), "Female".to_string())]))
+            .unwrap();
+        let cells = vec![
+            RtfCell::General(GeneralCell {
+                lines: vec!["男性".into(), "女性".into(), "".into(), "(N=198)".into()],
+                translated_lines: vec![],
+                styles: "".into(),
+                context: CellContext::default(),
+            }),
+            RtfCell::General(GeneralCell {
+                lines: vec!["男性".into(), "方案编号：AK112".into(), "合计".into()],
+                translated_lines: vec![],
+                styles: "".into(),
+                context: CellContext::default(),
+            }),
+        ];
+        let mut report = DryRunReport::default();
+        translator.estimate(&cells, &mut report);
+        assert_eq!(5, report.lines());
+        assert_eq!(1, report.cached);
+        assert_eq!(1, report.glossary);
+        assert_eq!(1, report.untranslatable);
+        assert_eq!(2, report.misses);
+        assert_eq!(8, report.usage.completion_tokens);
+        // nothing was sent to backend
+        assert!(translator
+            .cache
+            .read()
+            .unwrap()
+            .get(&("男性".into(), CellRole::Text))
+            .is_none());
+    }
+
+    #[test]
+    fn translate_language_pair_test() {
+        let translator = Translator::new(TranslatorParam {
+            config: LlmConfig {
+                model: "fake".into(),
+                system_prompt: "translate {source} into {target}".into(),
+                ..Default::default()
+            },
+            source_language: Language::English,
+            target_language: Language::Chinese,
+            ..param(Box::<Fake>::default())
+        });
+        assert_eq!("translate 英文 into 中文", translator.config.system_prompt);
+        let mut report = TranslateReport::default();
+        let cell = RtfCell::General(GeneralCell {
+            lines: vec!["Male".into(), "(N=198)".into(), "37.5 °C".into()],
+            translated_lines: vec![],
+            styles: "".into(),
+            context: CellContext::default(),
+        });
+        assert_eq!(
+            vec!["fake:Male", "(N=198)", "37.5 °C"],
+            translated_lines(translator.translate(&cell, &mut report))
+        );
+    }
+
+    #[test]
+    fn translate_with_context_test() {
+        #[derive(Debug)]
+        struct Contextual;
+        impl TranslationBackend for Contextual {
+            fn translate(
+                &self,
+                request: &TranslateRequest,
+            ) -> Result<Completion<String>, TranslateError> {
+                let translation = match (request.source, request.context.row.as_deref()) {
+                    ("合计", Some(_)) => "Total",
+                    ("例", _) if request.context.column.is_none() => "n",
+                    _ => "?",
+                };
+                Ok(Completion::free(translation.into()))
+            }
+        }
+        let translator = Translator::new(param(Box::new(Contextual)));
+        let mut report = TranslateReport::default();
+        let cell = RtfCell::General(GeneralCell {
+            lines: vec!["合计".into()],
+            translated_lines: vec![],
+            styles: "".into(),
+            context: CellContext {
+                title: None,
+                column: Some("试验组".into()),
+                row: Some("不良事件".into()),
+                role: CellRole::Value,
+            },
+        });
+        assert_eq!(
+            vec!["Total"],
+            translated_lines(translator.translate(&cell, &mut report))
+        );
+        let cell = RtfCell::General(GeneralCell {
+            lines: vec!["例".into()],
+            translated_lines: vec![],
+            styles: "".into(),
+            context: CellContext {
+                title: Some("表 14.1".into()),
+                role: CellRole::Header,
+                ..Default::default()
+            },
+        });
+        assert_eq!(
+            vec!["n"],
+            translated_lines(translator.translate(&cell, &mut report))
+        );
+        // translations in column headers are not reused by cells in table body
+        let cell = RtfCell::General(GeneralCell {
+            lines: vec!["例".into(), "合计".into()],
+            translated_lines: vec![],
+            styles: "".into(),
+            context: CellContext {
+                title: Some("表 14.1".into()),
+                column: Some("试验组".into()),
+                row: None,
+                role: CellRole::Label,
+            },
+        });
+        assert_eq!(
+            vec!["?", "?"],
+            translated_lines(translator.translate(&cell, &mut report))
+        );
     }
 }
@@ -1,3 +1,3 @@
 mod lang;
 
-pub use lang::contains_chinese;
+pub use lang::Language;
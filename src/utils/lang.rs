@@ -1,7 +1,179 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// languages of sources and translations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    Chinese,
+    English,
+    Japanese,
+}
+
+impl Language {
+    /// name of language used in the system prompt, which is written in chinese
+    pub fn name(&self) -> &str {
+        match self {
+            Language::Chinese => "中文",
+            Language::English => "英文",
+            Language::Japanese => "日文",
+        }
+    }
+
+    /// whether source contains text of this language, which needs to be translated
+    pub fn detect(&self, source: &str) -> bool {
+        match self {
+            Language::Chinese => contains_chinese(source),
+            Language::English => contains_english(source),
+            Language::Japanese => contains_japanese(source),
+        }
+    }
+
+    /// font and `\fcharset` used by outputs in this language
+    pub fn font(&self) -> (&str, u32) {
+        match self {
+            Language::Chinese => ("SimSun", 134),
+            Language::English => ("Times New Roman", 0),
+            Language::Japanese => ("MS Mincho", 128),
+        }
+    }
+
+    /// whether a font in font table is used for text of this language
+    pub fn uses_font(&self, name: &str, charset: Option<u32>) -> bool {
+        let fonts: &[&str] = match self {
+            Language::Chinese => &[
+                "SimSun",
+                "NSimSun",
+                "SimHei",
+                "KaiTi",
+                "FangSong",
+                "Microsoft YaHei",
+                "宋体",
+                "新宋体",
+                "黑体",
+                "楷体",
+                "仿宋",
+                "微软雅黑",
+            ],
+            Language::English => &["Times New Roman", "Arial", "Calibri", "Courier New"],
+            Language::Japanese => &["MS Mincho", "MS Gothic", "Yu Mincho", "Yu Gothic", "Meiryo"],
+        };
+        // ansi charset is shared by all western fonts, it tells nothing about the language
+        let (_, own_charset) = self.font();
+        fonts
+            .iter()
+            .any(|font| font.eq_ignore_ascii_case(name.trim()))
+            || (own_charset != 0 && charset == Some(own_charset))
+    }
+}
+
+impl Display for Language {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Language::Chinese => "chinese",
+            Language::English => "english",
+            Language::Japanese => "japanese",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// whether source contains chinese characters
+///
+/// punctuations, fullwidth forms and the ideographic space, such as ，, （ and U+3000, are not
+/// chinese, neither are symbols and latin letters out of ascii, such as ≥, °C, ± and µg
 pub fn contains_chinese(source: &str) -> bool {
-    if source.chars().any(|c| c > '\u{7F}') {
-        true
-    } else {
-        false
+    source.chars().any(is_chinese)
+}
+
+/// whether source contains japanese kana, or kanji shared with chinese
+fn contains_japanese(source: &str) -> bool {
+    source.chars().any(|c| {
+        matches!(c as u32,
+            // hiragana, katakana and phonetic extensions
+            0x3040..=0x30FF | 0x31F0..=0x31FF
+            // halfwidth katakana
+            | 0xFF66..=0xFF9F
+        ) || is_chinese(c)
+    })
+}
+
+/// whether source contains an english word, rtf control words are ignored
+///
+/// a single letter such as N in "(N=198)" is not a word
+fn contains_english(source: &str) -> bool {
+    let mut chars = source.chars().peekable();
+    let mut letters = 0;
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            // skip control word and its parameter, such as \super and \fs16
+            while chars.next_if(|c| c.is_ascii_alphabetic()).is_some() {}
+            while chars.next_if(|c| c.is_ascii_digit() || *c == '-').is_some() {}
+            letters = 0;
+            continue;
+        }
+        if c.is_ascii_alphabetic() {
+            letters += 1;
+            if letters > 1 {
+                return true;
+            }
+        } else {
+            letters = 0;
+        }
+    }
+    false
+}
+
+fn is_chinese(c: char) -> bool {
+    matches!(c as u32,
+        // cjk unified ideographs and extension a
+        0x3400..=0x4DBF | 0x4E00..=0x9FFF
+        // cjk compatibility ideographs
+        | 0xF900..=0xFAFF
+        // cjk unified ideographs extension b and later
+        | 0x20000..=0x3134F
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn contains_chinese_test() {
+        assert!(contains_chinese("男性"));
+        assert!(contains_chinese("Age (岁)"));
+        assert!(contains_chinese("方案编号："));
+        // punctuations and indents of numbers pass through
+        assert!(!contains_chinese("，"));
+        assert!(!contains_chinese("（N=198）"));
+        assert!(!contains_chinese("\u{3000}\u{3000}12 (5.0)"));
+        assert!(!contains_chinese("(N=198)"));
+        assert!(!contains_chinese("≥ 65"));
+        assert!(!contains_chinese("37.5 °C"));
+        assert!(!contains_chinese("12.3 ± 4.5 µg/mL"));
+        assert!(!contains_chinese("Dr. Müller"));
+        assert!(!contains_chinese(""));
+    }
+
+    #[test]
+    fn detect_test() {
+        assert!(Language::English.detect("Male"));
+        assert!(Language::English.detect(r"{\super a}Subjects"));
+        assert!(!Language::English.detect("(N=198)"));
+        assert!(!Language::English.detect(r"{\super a}12.5"));
+        assert!(!Language::English.detect("男性"));
+        assert!(Language::Japanese.detect("カテゴリ"));
+        assert!(Language::Japanese.detect("男性"));
+        assert!(!Language::Japanese.detect("37.5 °C"));
+    }
+
+    #[test]
+    fn uses_font_test() {
+        assert!(Language::Chinese.uses_font("SimSun", Some(0)));
+        assert!(Language::Chinese.uses_font("Unknown", Some(134)));
+        assert!(!Language::Chinese.uses_font("Times New Roman", Some(0)));
+        assert!(Language::English.uses_font("times new roman", None));
+        assert!(!Language::English.uses_font("Unknown", Some(0)));
     }
 }